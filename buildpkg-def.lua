@@ -65,13 +65,27 @@ function unpack_tarball(tarball, dest) end
 --- @return nil
 function copy(source, destination) end
 
---- Create a symbolic link.
+--- Create a symbolic link in the package directory pointing to source.
+--- The source does not need to exist while building, dangling links are reported by the package checks.
 --- 
 --- @param source string
 --- @param destination string
 --- @return nil
 function link(source, destination) end
 
+--- Run a shell command inside the root packages are installed to.
+--- Only available to install scriptlets (PRE_INSTALL, POST_INSTALL, POST_UPGRADE, PRE_REMOVE and
+--- POST_REMOVE), raises an error if the command fails.
+---
+--- @param command string
+--- @return nil
+function execute(command) end
+
+--- @diagnostic disable-next-line: doc-field-no-class
+--- @field ROOT string
+--- Root packages are installed to, only set for install scriptlets.
+ROOT = ""
+
 --- @diagnostic disable-next-line: doc-field-no-class
 --- @field arch string
 --- Current architecture. (e.g. "x86_64", "aarch64")
@@ -84,7 +98,7 @@ SRC_DIR = ""
 
 --- @diagnostic disable-next-line: doc-field-no-class
 --- @field PKG_DIR string
---- Package directory.
+--- Package directory. While a PACKAGE_<name> function runs, the directory of that subpackage.
 PKG_DIR = ""
 
 --- @class git_repo
@@ -122,4 +136,31 @@ git = {
     load = function(path) end
 }
 
+--- @class version_info
+--- @field epoch integer
+--- @field major integer
+--- @field minor integer
+--- @field patch integer
+--- @field pre string?
+--- @field revision integer?
+version_info = {}
+
+--- @class version
+version = {
+    --- Parse a version in the "[epoch:]major.minor.patch[~pre][-revision]" format.
+    --- Raises an error if the version is invalid.
+    --- 
+    --- @nodiscard
+    --- @param version string
+    --- @return version_info
+    parse = function(version) end,
 
+    --- Compare two versions.
+    --- Returns -1 if a is older than b, 0 if they are equal and 1 if a is newer.
+    --- 
+    --- @nodiscard
+    --- @param a string
+    --- @param b string
+    --- @return integer
+    compare = function(a, b) end
+}
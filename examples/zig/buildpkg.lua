@@ -6,7 +6,12 @@ INFO = {
   license = "MIT",
   dev = true,
   provides = {"zig"},
+  dependencies = {"zig-lib"},
   arch = {"x86_64", "aarch64"},
+  subpackages = {
+    { name = "zig-lib", description = "Standard library and runtime sources of the Zig toolchain" },
+    { name = "zig-docs", description = "Language reference of the Zig toolchain" },
+  },
 }
 
 ZIG_VERSION_FILE_URL="https://ziglang.org/download/index.json"
@@ -21,10 +26,12 @@ end
 
 ZIG_INDEX = {}
 
-function VERSION()
+function SOURCES()
   download(ZIG_VERSION_FILE_URL, "/index.json")
   ZIG_INDEX = json_decode(file_load("/index.json"))
+end
 
+function VERSION()
   return parse_version(ZIG_INDEX.master.version)
 end
 
@@ -53,6 +60,19 @@ function PACKAGE()
 
   copy(zig_dir .. "/zig", "/usr/lib/zig/zig")
   link("/usr/lib/zig/zig", "/usr/bin/zig")
-  copy(zig_dir .. "/lib", "/usr/lib/zig/lib")
   copy(zig_dir .. "/LICENSE", "/usr/share/licenses/zig/LICENSE")
 end
+
+function PACKAGE_zig_lib()
+  local zig_dir = "/zig-linux-" .. ARCH .. "-" .. FULL_VERSION
+
+  copy(zig_dir .. "/lib", "/usr/lib/zig/lib")
+  copy(zig_dir .. "/LICENSE", "/usr/share/licenses/zig-lib/LICENSE")
+end
+
+function PACKAGE_zig_docs()
+  local zig_dir = "/zig-linux-" .. ARCH .. "-" .. FULL_VERSION
+
+  copy(zig_dir .. "/doc", "/usr/share/doc/zig")
+  copy(zig_dir .. "/LICENSE", "/usr/share/licenses/zig-docs/LICENSE")
+end
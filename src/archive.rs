@@ -0,0 +1,372 @@
+use std::{collections::BTreeMap, fmt, fs, io::{self, Read, Write}, os::unix::fs::MetadataExt, path::Path, str::FromStr, thread};
+use bzip2::read::BzDecoder;
+use flate2::{read::GzDecoder, GzBuilder};
+use serde::Deserialize;
+use sha2::Digest;
+use tar::{Archive, Builder, Header, HeaderMode};
+use xz2::{read::XzDecoder, write::XzEncoder};
+
+/// Metadata files stored at the start of every package archive, in this order
+pub const METADATA_FILES: [&str; 2] = [".pkgfiles", "package.json"];
+
+/// Install scriptlets of the package, stored after the metadata files when the package has some
+pub const INSTALL_FILE: &str = ".INSTALL";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Codec {
+    Zstd,
+    Xz,
+    Gzip,
+    None,
+}
+
+impl Codec {
+    /// Default compression level of the codec
+    fn default_level(self) -> u32 {
+        match self {
+            Codec::Zstd => 19,
+            Codec::Xz => 6,
+            Codec::Gzip => 6,
+            Codec::None => 0,
+        }
+    }
+
+    /// Lowest compression level accepted by the codec
+    fn min_level(self) -> u32 {
+        match self {
+            Codec::Zstd => 1,
+            Codec::Xz | Codec::Gzip | Codec::None => 0,
+        }
+    }
+
+    /// Highest compression level accepted by the codec
+    fn max_level(self) -> u32 {
+        match self {
+            Codec::Zstd => 22,
+            Codec::Xz | Codec::Gzip => 9,
+            Codec::None => 0,
+        }
+    }
+}
+
+/// Compression applied to package archives, written as "codec[:level]" (e.g. "zstd:19")
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Compression {
+    pub codec: Codec,
+    pub level: u32,
+}
+
+impl Compression {
+    /// File extension of archives written with this compression
+    pub fn extension(&self) -> &'static str {
+        match self.codec {
+            Codec::Zstd => "tar.zst",
+            Codec::Xz => "tar.xz",
+            Codec::Gzip => "tar.gz",
+            Codec::None => "tar",
+        }
+    }
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression { codec: Codec::Gzip, level: Codec::Gzip.default_level() }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, level) = match s.split_once(':') {
+            Some((name, level)) => (name, Some(level)),
+            None => (s, None),
+        };
+
+        let codec = match name {
+            "zstd" | "zst" => Codec::Zstd,
+            "xz" => Codec::Xz,
+            "gzip" | "gz" => Codec::Gzip,
+            "none" => Codec::None,
+            _ => return Err(format!("unknown compression \"{}\" (expected zstd, xz, gzip or none)", name)),
+        };
+
+        let level = match level {
+            Some(_) if codec == Codec::None => return Err("compression none does not take a level".to_string()),
+            Some(level) => level.parse::<u32>()
+                .map_err(|_| format!("invalid compression level \"{}\"", level))?,
+            None => codec.default_level(),
+        };
+
+        if level < codec.min_level() || level > codec.max_level() {
+            return Err(format!("compression level for {} must be between {} and {}", name, codec.min_level(), codec.max_level()));
+        }
+
+        Ok(Compression { codec, level })
+    }
+}
+
+impl TryFrom<String> for Compression {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.codec {
+            Codec::Zstd => write!(f, "zstd:{}", self.level),
+            Codec::Xz => write!(f, "xz:{}", self.level),
+            Codec::Gzip => write!(f, "gzip:{}", self.level),
+            Codec::None => write!(f, "none"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct ArchiveOptions {
+    pub compression: Compression,
+    /// Number of zstd workers, 0 uses every available core
+    pub threads: u32,
+    /// Modification times newer than this are clamped to it (SOURCE_DATE_EPOCH)
+    pub source_date_epoch: Option<u64>,
+}
+
+/// Create a compressed package archive from the contents of a package directory
+///
+/// The output only depends on the package contents and the options: entries are sorted, owners
+/// are normalized and compressors write no timestamps.
+pub fn create_package_archive(pkg_dir: &Path, dest_path: &Path, options: &ArchiveOptions) -> io::Result<()> {
+    let dest_file = fs::File::create(dest_path)?;
+    let compression = options.compression;
+
+    match compression.codec {
+        Codec::Zstd => {
+            // Always use the multithreaded mode, its output does not depend on the worker count
+            // while the single threaded mode produces different frames
+            let threads = match options.threads {
+                0 => thread::available_parallelism().map(|n| n.get() as u32).unwrap_or(1),
+                n => n,
+            };
+
+            let mut encoder = zstd::Encoder::new(dest_file, compression.level as i32)?;
+            encoder.multithread(threads)?;
+            write_package_archive(pkg_dir, encoder, options)?.finish()?;
+        }
+        Codec::Xz => {
+            write_package_archive(pkg_dir, XzEncoder::new(dest_file, compression.level), options)?.finish()?;
+        }
+        Codec::Gzip => {
+            let encoder = GzBuilder::new()
+                .mtime(0)
+                .write(dest_file, flate2::Compression::new(compression.level));
+            write_package_archive(pkg_dir, encoder, options)?.finish()?;
+        }
+        Codec::None => {
+            write_package_archive(pkg_dir, dest_file, options)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Write the contents of a package directory as a tar stream, returning the inner writer
+///
+/// Metadata files are written first so installers can read them without unpacking the whole
+/// archive. Symlinks are stored as symlinks, modes are preserved and every entry is owned by root.
+fn write_package_archive<W: Write>(pkg_dir: &Path, writer: W, options: &ArchiveOptions) -> io::Result<W> {
+    let mut builder = Builder::new(writer);
+    builder.follow_symlinks(false);
+
+    for name in METADATA_FILES {
+        append_entry(&mut builder, &pkg_dir.join(name), Path::new(name), options)?;
+    }
+
+    if pkg_dir.join(INSTALL_FILE).is_file() {
+        append_entry(&mut builder, &pkg_dir.join(INSTALL_FILE), Path::new(INSTALL_FILE), options)?;
+    }
+
+    for entry in walkdir::WalkDir::new(pkg_dir).min_depth(1).sort_by_file_name() {
+        let entry = entry?;
+        let relative_path = entry.path().strip_prefix(pkg_dir)
+            .map_err(io::Error::other)?;
+
+        if is_metadata_file(relative_path) {
+            continue;
+        }
+
+        append_entry(&mut builder, entry.path(), relative_path, options)?;
+    }
+
+    builder.into_inner()
+}
+
+/// Returns true if the path (relative to the package root) is one of the package metadata files
+pub fn is_metadata_file(relative_path: &Path) -> bool {
+    METADATA_FILES.iter().chain([&INSTALL_FILE]).any(|name| relative_path == Path::new(name))
+}
+
+fn append_entry<W: Write>(builder: &mut Builder<W>, path: &Path, archive_path: &Path, options: &ArchiveOptions) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+
+    let mut header = Header::new_gnu();
+    header.set_metadata_in_mode(&metadata, HeaderMode::Complete);
+    header.set_mode(metadata.mode() & 0o7777);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_username("root")?;
+    header.set_groupname("root")?;
+
+    // Access and change times are never useful in a package and differ between builds
+    if let Some(gnu) = header.as_gnu_mut() {
+        gnu.set_atime(0);
+        gnu.set_ctime(0);
+    }
+
+    if let Some(epoch) = options.source_date_epoch
+        && header.mtime()? > epoch
+    {
+        header.set_mtime(epoch);
+    }
+
+    let file_type = metadata.file_type();
+
+    if file_type.is_symlink() {
+        header.set_size(0);
+        builder.append_link(&mut header, archive_path, fs::read_link(path)?)
+    } else if file_type.is_dir() {
+        header.set_size(0);
+        builder.append_data(&mut header, archive_path, io::empty())
+    } else if file_type.is_file() {
+        builder.append_data(&mut header, archive_path, fs::File::open(path)?)
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Unsupported file type in package: {:?}", path),
+        ))
+    }
+}
+
+/// Open a package archive, detecting its compression from the first bytes of the file
+pub fn open_package_archive(path: &Path) -> io::Result<Archive<Box<dyn Read>>> {
+    let mut file = fs::File::open(path)?;
+
+    let mut magic = [0u8; 6];
+    let read = file.read(&mut magic)?;
+    let magic = &magic[..read];
+
+    let file = io::Cursor::new(magic.to_vec()).chain(file);
+
+    let reader: Box<dyn Read> = if magic.starts_with(&[0x1f, 0x8b]) {
+        Box::new(GzDecoder::new(file))
+    } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+        Box::new(XzDecoder::new(file))
+    } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        Box::new(zstd::Decoder::new(file)?)
+    } else if magic.starts_with(b"BZh") {
+        Box::new(BzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+
+    Ok(Archive::new(reader))
+}
+
+/// Read the manifest (.pkgfiles), package.json and install scriptlets (.INSTALL, if any) stored
+/// at the start of a package archive
+pub fn read_metadata_files(path: &Path) -> io::Result<(String, String, Option<String>)> {
+    let mut archive = open_package_archive(path)?;
+
+    let mut manifest = None;
+    let mut package_json = None;
+    let mut install = None;
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let entry_path = entry.path()?.into_owned();
+
+        let target = if entry_path == Path::new(METADATA_FILES[0]) {
+            &mut manifest
+        } else if entry_path == Path::new(METADATA_FILES[1]) {
+            &mut package_json
+        } else if entry_path == Path::new(INSTALL_FILE) {
+            &mut install
+        } else if manifest.is_some() && package_json.is_some() {
+            // The scriptlets can only follow the metadata files
+            break;
+        } else {
+            continue;
+        };
+
+        let mut content = String::new();
+        entry.read_to_string(&mut content)?;
+        *target = Some(content);
+    }
+
+    match (manifest, package_json) {
+        (Some(manifest), Some(package_json)) => Ok((manifest, package_json, install)),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidData, format!("{:?} is not a vrdpkg package, metadata files are missing", path))),
+    }
+}
+
+/// Describe every entry of a package archive, keyed by path
+fn describe_entries(path: &Path) -> io::Result<BTreeMap<String, String>> {
+    let mut archive = open_package_archive(path)?;
+    let mut entries = BTreeMap::new();
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let header = entry.header().clone();
+        let entry_path = entry.path()?.to_string_lossy().into_owned();
+
+        let mut hasher = sha2::Sha256::new();
+        io::copy(&mut entry, &mut hasher)?;
+
+        let link = entry.link_name()?
+            .map(|link| format!(" link={}", link.display()))
+            .unwrap_or_default();
+
+        entries.insert(entry_path, format!(
+            "type={:?} mode={:o} uid={} gid={} user={} group={} mtime={} size={} sha256={:x}{}",
+            header.entry_type(),
+            header.mode()?,
+            header.uid()?,
+            header.gid()?,
+            header.username().ok().flatten().unwrap_or(""),
+            header.groupname().ok().flatten().unwrap_or(""),
+            header.mtime()?,
+            header.size()?,
+            hasher.finalize(),
+            link,
+        ));
+    }
+
+    Ok(entries)
+}
+
+/// Compare the entries of two package archives, returning a description of every difference
+pub fn diff_package_archives(first: &Path, second: &Path) -> io::Result<Vec<String>> {
+    let first_entries = describe_entries(first)?;
+    let second_entries = describe_entries(second)?;
+
+    let mut differences = Vec::new();
+
+    for (path, first_description) in &first_entries {
+        match second_entries.get(path) {
+            Some(second_description) if second_description == first_description => {}
+            Some(second_description) => differences.push(format!("{}:\n  - {}\n  + {}", path, first_description, second_description)),
+            None => differences.push(format!("{}: only in first build", path)),
+        }
+    }
+
+    for path in second_entries.keys() {
+        if !first_entries.contains_key(path) {
+            differences.push(format!("{}: only in second build", path));
+        }
+    }
+
+    Ok(differences)
+}
@@ -0,0 +1,463 @@
+use std::{collections::BTreeMap, fs, path::{Path, PathBuf}};
+use mlua::{FromLuaMulti, Function, IntoLuaMulti, Lua};
+
+use crate::archive::{create_package_archive, diff_package_archives, ArchiveOptions, Compression};
+use crate::database::Database;
+use crate::dependency::Dependency;
+use crate::error::{Error, Result};
+use crate::file_operations::sha256sum_file;
+use crate::lua_functions::{register_git_object, register_lua_functions, register_version_object, set_package_dir, SourceRepositories};
+use crate::manifest::Manifest;
+use crate::package_info::{lua_get_package_info, lua_get_subpackages, FinalPackageInfo, PackageInfo};
+use crate::qa::{check_package, LinkTargets, QaCheck, QaLevel};
+use crate::scriptlet::{embed_scriptlets, HOOKS};
+use crate::shlib::add_library_relations;
+use crate::source::fetch_sources;
+use crate::strip::strip_package;
+use crate::version::Version;
+
+/// Environment variable holding the timestamp used for reproducible archives
+pub const SOURCE_DATE_EPOCH_ENV: &str = "SOURCE_DATE_EPOCH";
+
+/// Directory of the project holding the package directory of every subpackage
+pub const SUBPACKAGES_DIR: &str = "subpkg";
+
+#[derive(Clone)]
+pub struct BuildOptions {
+    pub buildpkg_lua: PathBuf,
+    pub working_dir: PathBuf,
+    pub clean_before: bool,
+    pub clean_after: bool,
+    pub skip_check: bool,
+    pub compression: Compression,
+    pub compression_threads: u32,
+    /// Levels of the package checks from the configuration file
+    pub qa: BTreeMap<QaCheck, QaLevel>,
+    /// Root whose installed packages provide the shared libraries the package needs
+    pub root: PathBuf,
+}
+
+/// Runs every phase of the build script and writes the package archive, followed by one archive
+/// per subpackage and per debug package, returning their paths
+pub fn build_package(options: &BuildOptions) -> Result<Vec<PathBuf>> {
+    let working_dir = &options.working_dir;
+
+    if options.clean_before {
+        clean_project(working_dir)?;
+    }
+
+    let lua = Lua::new();
+
+    if !working_dir.join("src").exists() {
+        fs::create_dir(working_dir.join("src"))?;
+    }
+
+    let src_dir_value = working_dir.join("src");
+
+    if !working_dir.join("pkg").exists() {
+        fs::create_dir(working_dir.join("pkg"))?;
+    }
+
+    let pkg_dir_value = working_dir.join("pkg");
+
+    let (_, chunk_name) = load_build_script(&lua, &options.buildpkg_lua, &src_dir_value, &pkg_dir_value)?;
+
+    let mut package_info = lua_get_package_info(&lua)?;
+
+    if package_info.dev {
+        println!("Building package in dev mode");
+    }
+
+    let version_function_exists = function_exists(&lua, "VERSION");
+
+    if package_info.version.is_none() && !version_function_exists {
+        return Err(Error::InvalidPackage("version field missing and VERSION function not found".to_string()));
+    }
+
+    if package_info.version.is_some() && version_function_exists {
+        return Err(Error::InvalidPackage("version field and VERSION function both found".to_string()));
+    }
+
+    // Declared sources are fetched first so SOURCES and VERSION can inspect them
+    if !package_info.sources.is_empty() {
+        println!("Fetching sources...");
+        fetch_sources(&lua, &package_info.sources, &src_dir_value)?;
+    }
+
+    // Declared sources can replace the SOURCES phase
+    if package_info.sources.is_empty() {
+        run_phase(&lua, "SOURCES", "Getting sources...")?;
+    } else {
+        run_optional_phase(&lua, "SOURCES", "Getting sources...")?;
+    }
+
+    if package_info.version.is_none() {
+        package_info.version = run_function(&lua, "VERSION", ())?;
+    }
+
+    let Some(version) = package_info.version.clone() else {
+        return Err(Error::InvalidPackage("version field missing and VERSION function returned nil".to_string()));
+    };
+
+    let version: Version = version.parse()?;
+
+    println!("\n- {} {} ({}) maintained by {}\n", package_info.name, version, package_info.license, package_info.maintainers.join(", "));
+
+    if !package_info.arch.contains(&std::env::consts::ARCH.to_string()) {
+        return Err(Error::InvalidPackage(format!("package not available for host architecture {}", std::env::consts::ARCH)));
+    }
+
+    let subpackages = lua_get_subpackages(&lua, &package_info)?;
+
+    for subpackage in &subpackages {
+        let function_name = package_function_name(&subpackage.name);
+
+        if !function_exists(&lua, &function_name) {
+            return Err(Error::InvalidPackage(format!("{} function not found for subpackage {}", function_name, subpackage.name)));
+        }
+    }
+
+    // Scriptlets run without the build script, so hooks live in the file named by install
+    if let Some(hook) = HOOKS.iter().find(|hook| function_exists(&lua, hook)) {
+        return Err(Error::InvalidPackage(format!("{} defined in {}, install hooks belong in the file named by the install field", hook, chunk_name)));
+    }
+
+    run_phase(&lua, "PREPARE", "Preparing...")?;
+    run_optional_phase(&lua, "BUILD", "Building...")?;
+
+    if options.skip_check {
+        if function_exists(&lua, "CHECK") {
+            println!("Skipping checks (--nocheck)");
+        }
+    } else {
+        run_optional_phase(&lua, "CHECK", "Checking...")?;
+    }
+
+    run_phase(&lua, "PACKAGE", "Packaging...")?;
+
+    embed_scriptlets(package_info.install.as_deref(), working_dir, &pkg_dir_value)?;
+
+    let mut packages = vec![(package_info, pkg_dir_value)];
+
+    for subpackage in subpackages {
+        if !subpackage.arch.contains(&std::env::consts::ARCH.to_string()) {
+            println!("Skipping subpackage {}, not available for host architecture {}", subpackage.name, std::env::consts::ARCH);
+            continue;
+        }
+
+        let subpackage_dir = working_dir.join(SUBPACKAGES_DIR).join(&subpackage.name);
+        fs::create_dir_all(&subpackage_dir)?;
+
+        set_package_dir(&lua, subpackage_dir.clone())?;
+        run_phase(&lua, &package_function_name(&subpackage.name), &format!("Packaging {}...", subpackage.name))?;
+
+        embed_scriptlets(subpackage.install.as_deref(), working_dir, &subpackage_dir)?;
+
+        packages.push((subpackage, subpackage_dir));
+    }
+
+    add_library_relations(&mut packages, &options.root)?;
+
+    let mut debug_packages = Vec::new();
+
+    for (info, pkg_dir) in packages.iter().filter(|(info, _)| info.strip) {
+        let debug_package = debug_package_info(info, &version);
+
+        if packages.iter().any(|(other, _)| other.name == debug_package.name) {
+            return Err(Error::InvalidPackage(format!("{} is the name of the debug package of {}, set its strip field to false", debug_package.name, info.name)));
+        }
+
+        let debug_dir = working_dir.join(SUBPACKAGES_DIR).join(&debug_package.name);
+
+        if debug_dir.exists() {
+            fs::remove_dir_all(&debug_dir)?;
+        }
+
+        if strip_package(pkg_dir, &debug_dir, &info.no_strip)? {
+            debug_packages.push((debug_package, debug_dir));
+        }
+    }
+
+    let installed = Database::open(&options.root).packages()?;
+
+    let link_targets = LinkTargets {
+        pkg_dirs: packages.iter().chain(&debug_packages).map(|(_, pkg_dir)| pkg_dir.as_path()).collect(),
+        root: &options.root,
+        installed: installed.iter()
+            .flat_map(|package| package.manifest.entries.iter().map(|entry| entry.path.as_str()))
+            .collect(),
+    };
+
+    let mut qa_errors = Vec::new();
+
+    for (info, pkg_dir) in &packages {
+        let levels = options.qa.iter().chain(&info.qa)
+            .map(|(check, level)| (*check, *level))
+            .collect();
+
+        qa_errors.extend(check_package(&info.name, pkg_dir, working_dir, &levels, &link_targets)?);
+    }
+
+    if !qa_errors.is_empty() {
+        return Err(Error::QaFailed(qa_errors));
+    }
+
+    packages.extend(debug_packages);
+
+    let source_date_epoch = source_date_epoch(&lua)?;
+
+    match source_date_epoch {
+        Some(epoch) => println!("Clamping file times to {}", epoch),
+        None => println!("Warning: {} is not set and no git source was used, the package will not be reproducible", SOURCE_DATE_EPOCH_ENV),
+    }
+
+    let archive_options = ArchiveOptions {
+        compression: options.compression,
+        threads: options.compression_threads,
+        source_date_epoch,
+    };
+
+    let mut archives = Vec::with_capacity(packages.len());
+
+    for (info, pkg_dir) in packages {
+        archives.push(archive_package(info, &pkg_dir, working_dir, &archive_options)?);
+    }
+
+    if options.clean_after {
+        clean_project(working_dir)?;
+    }
+
+    Ok(archives)
+}
+
+/// Registers the build functions and runs the top level of the build script, returning its
+/// source and chunk name
+pub fn load_build_script(lua: &Lua, buildpkg_lua: &Path, src_dir: &Path, pkg_dir: &Path) -> Result<(String, String)> {
+    register_lua_functions(lua, src_dir.to_path_buf(), pkg_dir.to_path_buf())?;
+    register_git_object(lua, src_dir.to_path_buf())?;
+    register_version_object(lua)?;
+
+    let lua_code = fs::read_to_string(buildpkg_lua)?;
+
+    let chunk_name = buildpkg_lua.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "buildpkg.lua".to_string());
+
+    lua.load(&lua_code).set_name(format!("@{}", chunk_name)).exec()?;
+
+    Ok((lua_code, chunk_name))
+}
+
+/// Write the manifest and package.json of a package directory and archive it in the working directory
+fn archive_package(package_info: PackageInfo, pkg_dir: &Path, working_dir: &Path, archive_options: &ArchiveOptions) -> Result<PathBuf> {
+    let manifest = Manifest::from_dir(pkg_dir)?;
+    fs::write(pkg_dir.join(".pkgfiles"), manifest.to_string())?;
+
+    let final_package_info = FinalPackageInfo::new(package_info, &manifest)?;
+
+    let final_package_info_json = serde_json::to_string(&final_package_info)?;
+    fs::write(pkg_dir.join("package.json"), final_package_info_json)?;
+
+    // creates a tarball of the pkg directory named after the project version like project-version-arch.tar.zst
+    let tarball_name = format!("{}-{}-{}.{}", final_package_info.name, final_package_info.version, std::env::consts::ARCH, archive_options.compression.extension());
+    let tarball_path = working_dir.join(tarball_name);
+
+    println!("Creating package {:?} ({})", tarball_path, archive_options.compression);
+
+    create_package_archive(pkg_dir, &tarball_path, archive_options)
+        .map_err(Error::Archive)?;
+
+    Ok(tarball_path)
+}
+
+/// Package holding the debug info split off the binaries of a package, named <name>-debug
+fn debug_package_info(package_info: &PackageInfo, version: &Version) -> PackageInfo {
+    PackageInfo {
+        name: format!("{}-debug", package_info.name),
+        description: format!("Debug information for {}", package_info.name),
+        version: package_info.version.clone(),
+        license: package_info.license.clone(),
+        dev: package_info.dev,
+        dependencies: vec![Dependency::exact(&package_info.name, version)],
+        build_dependencies: Vec::new(),
+        optional_dependencies: Vec::new(),
+        conflicts: Vec::new(),
+        provides: Vec::new(),
+        replaces: Vec::new(),
+        backup: Vec::new(),
+        install: None,
+        sources: Vec::new(),
+        strip: false,
+        no_strip: Vec::new(),
+        qa: BTreeMap::new(),
+        arch: package_info.arch.clone(),
+        url: package_info.url.clone(),
+        maintainers: package_info.maintainers.clone(),
+    }
+}
+
+/// Name of the function packaging a subpackage: PACKAGE_ followed by the name, with characters
+/// Lua does not allow in identifiers replaced by underscores (PACKAGE_zig_docs for zig-docs)
+pub fn package_function_name(name: &str) -> String {
+    let name: String = name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    format!("PACKAGE_{}", name)
+}
+
+/// Removes the src, pkg and subpackage directories of a project
+pub fn clean_project(working_dir: &Path) -> Result<()> {
+    if working_dir.join("src").exists() {
+        fs::remove_dir_all(working_dir.join("src"))?;
+    }
+
+    if working_dir.join("pkg").exists() {
+        fs::remove_dir_all(working_dir.join("pkg"))?;
+    }
+
+    if working_dir.join(SUBPACKAGES_DIR).exists() {
+        fs::remove_dir_all(working_dir.join(SUBPACKAGES_DIR))?;
+    }
+
+    Ok(())
+}
+
+/// Builds the package twice from clean trees and checks that both archives are identical
+pub fn verify_reproducible(options: &BuildOptions) -> Result<()> {
+    let options = BuildOptions {
+        clean_before: true,
+        ..options.clone()
+    };
+
+    let file_name = |path: &Path| path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let mut first_copies = BTreeMap::new();
+
+    for first_build in build_package(&options)? {
+        let first_copy = PathBuf::from(format!("{}.first", first_build.display()));
+        fs::rename(&first_build, &first_copy)?;
+        first_copies.insert(file_name(&first_build), first_copy);
+    }
+
+    println!("\nRebuilding to verify reproducibility...\n");
+
+    let second_builds: BTreeMap<String, PathBuf> = build_package(&options)?.into_iter()
+        .map(|second_build| (file_name(&second_build), second_build))
+        .collect();
+
+    let mut reproducible = true;
+
+    // Both builds must produce the same archives, e.g. a debug package only appearing once fails
+    for (name, first_copy) in first_copies.iter().filter(|(name, _)| !second_builds.contains_key(*name)) {
+        reproducible = false;
+        eprintln!("\n{}: only produced by the first build, kept at {:?}", name, first_copy);
+    }
+
+    for (name, second_build) in second_builds.iter().filter(|(name, _)| !first_copies.contains_key(*name)) {
+        reproducible = false;
+        eprintln!("\n{}: only produced by the second build at {:?}", name, second_build);
+    }
+
+    for (name, second_build) in &second_builds {
+        let Some(first_copy) = first_copies.get(name) else {
+            continue;
+        };
+
+        let first_hash = sha256sum_file(first_copy)?;
+        let second_hash = sha256sum_file(second_build)?;
+
+        if first_hash == second_hash {
+            fs::remove_file(first_copy)?;
+            println!("\n{:?} is reproducible (sha256 {})", second_build, second_hash);
+            continue;
+        }
+
+        reproducible = false;
+
+        eprintln!("\n{:?}: archives differ ({} != {})", second_build, first_hash, second_hash);
+
+        let differences = diff_package_archives(first_copy, second_build)?;
+
+        if differences.is_empty() {
+            eprintln!("Archive entries are identical, the compressed streams differ");
+        }
+
+        for difference in differences {
+            eprintln!("{}", difference);
+        }
+
+        eprintln!("The first build was kept at {:?}", first_copy);
+    }
+
+    if reproducible {
+        Ok(())
+    } else {
+        Err(Error::NotReproducible)
+    }
+}
+
+/// Timestamp used to clamp file times: SOURCE_DATE_EPOCH, or the newest commit time of the git sources
+fn source_date_epoch(lua: &Lua) -> Result<Option<u64>> {
+    if let Ok(value) = std::env::var(SOURCE_DATE_EPOCH_ENV) {
+        return value.parse::<u64>()
+            .map(Some)
+            .map_err(|_| Error::InvalidPackage(format!("{} is not a valid timestamp: {}", SOURCE_DATE_EPOCH_ENV, value)));
+    }
+
+    let Some(repositories) = lua.app_data_ref::<SourceRepositories>() else {
+        return Ok(None);
+    };
+
+    let mut epoch = None;
+
+    for path in &repositories.0 {
+        let repo = git2::Repository::open(path)?;
+        let time = repo.head()?.peel_to_commit()?.time().seconds();
+
+        epoch = epoch.max(u64::try_from(time).ok());
+    }
+
+    Ok(epoch)
+}
+
+pub fn function_exists(lua: &Lua, function_name: &str) -> bool {
+    lua.globals().get::<Function>(function_name).is_ok()
+}
+
+/// Calls a global Lua function, returning None if the script does not define it
+fn run_function_if_exists<R: FromLuaMulti>(lua: &Lua, function_name: &str, args: impl IntoLuaMulti) -> Option<mlua::Result<R>> {
+    let function: Function = lua.globals().get(function_name).ok()?;
+
+    Some(function.call::<R>(args))
+}
+
+fn run_function<R: FromLuaMulti>(lua: &Lua, function_name: &str, args: impl IntoLuaMulti) -> Result<R> {
+    match run_function_if_exists(lua, function_name, args) {
+        Some(Ok(r)) => Ok(r),
+        Some(Err(source)) => Err(Error::Phase { phase: function_name.to_string(), source }),
+        None => Err(Error::InvalidPackage(format!("function {} not found", function_name))),
+    }
+}
+
+/// Runs a phase the script is required to define
+fn run_phase(lua: &Lua, phase: &str, message: &str) -> Result<()> {
+    if !function_exists(lua, phase) {
+        return Err(Error::InvalidPackage(format!("{} phase not defined", phase)));
+    }
+
+    run_optional_phase(lua, phase, message)
+}
+
+/// Runs a phase if the script defines it, reporting which phase failed on error
+fn run_optional_phase(lua: &Lua, phase: &str, message: &str) -> Result<()> {
+    if !function_exists(lua, phase) {
+        return Ok(());
+    }
+
+    println!("{}", message);
+
+    match run_function_if_exists::<()>(lua, phase, ()) {
+        Some(Err(source)) => Err(Error::Phase { phase: phase.to_string(), source }),
+        _ => Ok(()),
+    }
+}
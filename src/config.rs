@@ -0,0 +1,66 @@
+use std::{collections::BTreeMap, fs, io, path::PathBuf};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::archive::Compression;
+use crate::qa::{QaCheck, QaLevel};
+
+/// Default location of the vrdpkg configuration file
+pub const DEFAULT_CONFIG_PATH: &str = "/etc/vrdpkg/config.json";
+
+/// Environment variable overriding the configuration file location
+pub const CONFIG_PATH_ENV: &str = "VRDPKG_CONFIG";
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Failed to read {0}: {1}")]
+    Io(PathBuf, io::Error),
+    #[error("Invalid configuration in {0}: {1}")]
+    Parse(PathBuf, serde_json::Error),
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Default package compression, e.g. "zstd:19" or "gzip"
+    pub compression: Option<Compression>,
+    /// Worker threads used by multithreaded compressors, 0 uses every available core
+    pub compression_threads: Option<u32>,
+    /// Repositories packages are installed from by name, the first one wins when several hold the
+    /// same version of a package
+    pub repositories: Vec<RepositoryConfig>,
+    /// Level of the package checks run before archiving, e.g. {"world-writable": "error"}, the qa
+    /// field of INFO overrides it for a package
+    pub qa: BTreeMap<QaCheck, QaLevel>,
+}
+
+/// A repository of package archives with an index.json, as written by `vrdpkg repo`
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RepositoryConfig {
+    pub name: String,
+    /// A directory, a file:// URL or an http(s):// URL
+    pub url: String,
+}
+
+impl Config {
+    /// Returns the path of the configuration file in use
+    pub fn path() -> PathBuf {
+        std::env::var_os(CONFIG_PATH_ENV)
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_PATH))
+    }
+
+    /// Loads the configuration file, falling back to the defaults if it does not exist
+    pub fn load() -> Result<Config, ConfigError> {
+        let path = Config::path();
+
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(ConfigError::Io(path, e)),
+        };
+
+        serde_json::from_str(&content).map_err(|e| ConfigError::Parse(path, e))
+    }
+}
@@ -0,0 +1,123 @@
+use std::{collections::HashMap, fs, io, path::{Path, PathBuf}};
+
+use crate::archive::{INSTALL_FILE, METADATA_FILES};
+use crate::dependency::validate_name;
+use crate::error::{Error, Result};
+use crate::manifest::{EntryKind, Manifest};
+use crate::package_info::FinalPackageInfo;
+
+/// Directory of the installed-package database, relative to the root
+pub const DATABASE_DIR: &str = "var/lib/vrdpkg";
+
+/// A package recorded in the database
+#[derive(Clone, Debug)]
+pub struct InstalledPackage {
+    pub info: FinalPackageInfo,
+    pub manifest: Manifest,
+    /// Install scriptlets (.INSTALL), kept to run the removal hooks
+    pub install: Option<String>,
+}
+
+/// Database of the packages installed under a root
+///
+/// Every package has a directory at `<root>/var/lib/vrdpkg/local/<name>` holding the
+/// package.json, .pkgfiles and .INSTALL it was installed from.
+pub struct Database {
+    root: PathBuf,
+}
+
+impl Database {
+    /// Open the database of a root, its directories are created by the first install
+    pub fn open(root: &Path) -> Database {
+        Database { root: root.to_path_buf() }
+    }
+
+    /// Directory holding the database, `<root>/var/lib/vrdpkg`
+    pub fn dir(&self) -> PathBuf {
+        self.root.join(DATABASE_DIR)
+    }
+
+    fn local_dir(&self) -> PathBuf {
+        self.dir().join("local")
+    }
+
+    /// Directory recording an installed package, `<root>/var/lib/vrdpkg/local/<name>`
+    pub fn package_dir(&self, name: &str) -> Result<PathBuf> {
+        validate_name(name).map_err(|e| Error::InvalidPackage(format!("{}: {}", name, e)))?;
+
+        Ok(self.local_dir().join(name))
+    }
+
+    /// Read an installed package, returning None if it is not installed
+    pub fn get(&self, name: &str) -> Result<Option<InstalledPackage>> {
+        let package_dir = self.package_dir(name)?;
+
+        if !package_dir.is_dir() {
+            return Ok(None);
+        }
+
+        let package_json = fs::read_to_string(package_dir.join(METADATA_FILES[1]))?;
+        let manifest = fs::read_to_string(package_dir.join(METADATA_FILES[0]))?;
+
+        let install = match fs::read_to_string(package_dir.join(INSTALL_FILE)) {
+            Ok(install) => Some(install),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Some(InstalledPackage {
+            info: serde_json::from_str(&package_json)?,
+            manifest: manifest.parse()?,
+            install,
+        }))
+    }
+
+    /// Every installed package, sorted by name
+    pub fn packages(&self) -> Result<Vec<InstalledPackage>> {
+        if !self.local_dir().is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let mut names = fs::read_dir(self.local_dir())?
+            .map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().into_owned()))
+            .collect::<io::Result<Vec<String>>>()?;
+        // Hidden entries are staged or backed up by a transaction, not installed packages
+        names.retain(|name| !name.starts_with('.'));
+        names.sort();
+
+        let mut packages = Vec::with_capacity(names.len());
+
+        for name in names {
+            if let Some(package) = self.get(&name)? {
+                packages.push(package);
+            }
+        }
+
+        Ok(packages)
+    }
+}
+
+/// Write the records of a package, package.json, .pkgfiles and .INSTALL, into a database directory
+pub fn write_package(package_dir: &Path, package: &InstalledPackage) -> Result<()> {
+    fs::create_dir_all(package_dir)?;
+
+    fs::write(package_dir.join(METADATA_FILES[0]), package.manifest.to_string())?;
+    fs::write(package_dir.join(METADATA_FILES[1]), serde_json::to_string(&package.info)?)?;
+
+    if let Some(install) = &package.install {
+        fs::write(package_dir.join(INSTALL_FILE), install)?;
+    }
+
+    Ok(())
+}
+
+/// Map every file and symlink of the packages to the name of the package owning it
+///
+/// Directories are left out as they are shared between packages.
+pub fn file_owners(packages: &[InstalledPackage]) -> HashMap<&str, &str> {
+    packages.iter()
+        .flat_map(|package| package.manifest.entries.iter()
+            .filter(|entry| entry.kind != EntryKind::Dir)
+            .map(|entry| (entry.path.as_str(), package.info.name.as_str())))
+        .collect()
+}
@@ -0,0 +1,355 @@
+use std::{fmt, str::FromStr};
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::package_info::FinalPackageInfo;
+use crate::version::Version;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operator {
+    Less,
+    LessEqual,
+    Equal,
+    GreaterEqual,
+    Greater,
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operator::Less => write!(f, "<"),
+            Operator::LessEqual => write!(f, "<="),
+            Operator::Equal => write!(f, "="),
+            Operator::GreaterEqual => write!(f, ">="),
+            Operator::Greater => write!(f, ">"),
+        }
+    }
+}
+
+/// Version requirement of a dependency, e.g. ">=3.0.0"
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Constraint {
+    pub operator: Operator,
+    pub version: Version,
+}
+
+impl Constraint {
+    /// Returns true if the version satisfies the constraint
+    ///
+    /// A constraint without a revision ignores the revision of the version, so "=1.3.1" accepts
+    /// every revision of 1.3.1 while "=1.3.1-2" only accepts that one.
+    pub fn matches(&self, version: &Version) -> bool {
+        let version = match self.version.revision {
+            Some(_) => version.clone(),
+            None => Version { revision: None, ..version.clone() },
+        };
+
+        match self.operator {
+            Operator::Less => version < self.version,
+            Operator::LessEqual => version <= self.version,
+            Operator::Equal => version == self.version,
+            Operator::GreaterEqual => version >= self.version,
+            Operator::Greater => version > self.version,
+        }
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.operator, self.version)
+    }
+}
+
+/// A package name with an optional version constraint, written as "name[op version]"
+/// (e.g. "openssl>=3.0.0"), used by dependencies, conflicts and replaces
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Dependency {
+    pub name: String,
+    pub constraint: Option<Constraint>,
+}
+
+impl Dependency {
+    /// A dependency on exactly one version of a package
+    pub fn exact(name: &str, version: &Version) -> Dependency {
+        Dependency {
+            name: name.to_string(),
+            constraint: Some(Constraint { operator: Operator::Equal, version: version.clone() }),
+        }
+    }
+
+    /// Returns true if the package satisfies the dependency, by its own name or one of its provides
+    ///
+    /// A versioned dependency is only satisfied by a provide that carries a version.
+    pub fn is_satisfied_by(&self, info: &FinalPackageInfo) -> bool {
+        if self.name == info.name && self.constraint.as_ref().is_none_or(|constraint| constraint.matches(&info.version)) {
+            return true;
+        }
+
+        info.provides.iter().any(|provide| provide.name == self.name && match (&self.constraint, &provide.version) {
+            (None, _) => true,
+            (Some(constraint), Some(version)) => constraint.matches(version),
+            (Some(_), None) => false,
+        })
+    }
+}
+
+impl FromStr for Dependency {
+    type Err = Error;
+
+    fn from_str(dependency: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: String| Error::InvalidDependency(dependency.to_string(), reason);
+
+        let Some(start) = dependency.find(['<', '>', '=']) else {
+            validate_name(dependency).map_err(invalid)?;
+            return Ok(Dependency { name: dependency.to_string(), constraint: None });
+        };
+
+        let (name, rest) = dependency.split_at(start);
+        validate_name(name).map_err(invalid)?;
+
+        let (operator, version) = [
+            ("<=", Operator::LessEqual),
+            (">=", Operator::GreaterEqual),
+            ("<", Operator::Less),
+            (">", Operator::Greater),
+            ("=", Operator::Equal),
+        ]
+            .into_iter()
+            .find_map(|(symbol, operator)| rest.strip_prefix(symbol).map(|version| (operator, version)))
+            .ok_or_else(|| invalid("expected an operator".to_string()))?;
+
+        let version = Version::parse_partial(version).map_err(|e| invalid(e.to_string()))?;
+
+        Ok(Dependency {
+            name: name.to_string(),
+            constraint: Some(Constraint { operator, version }),
+        })
+    }
+}
+
+impl TryFrom<String> for Dependency {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Dependency> for String {
+    fn from(dependency: Dependency) -> Self {
+        dependency.to_string()
+    }
+}
+
+impl fmt::Display for Dependency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+
+        if let Some(constraint) = &self.constraint {
+            write!(f, "{}", constraint)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A name provided by a package, optionally with the version it provides, written as
+/// "name[=version]" (e.g. "sh=5.2")
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Provide {
+    pub name: String,
+    pub version: Option<Version>,
+}
+
+impl FromStr for Provide {
+    type Err = Error;
+
+    fn from_str(provide: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: String| Error::InvalidDependency(provide.to_string(), reason);
+
+        let (name, version) = match provide.split_once('=') {
+            Some((name, version)) => (name, Some(Version::parse_partial(version).map_err(|e| invalid(e.to_string()))?)),
+            None => (provide, None),
+        };
+
+        validate_name(name).map_err(invalid)?;
+
+        Ok(Provide { name: name.to_string(), version })
+    }
+}
+
+impl TryFrom<String> for Provide {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Provide> for String {
+    fn from(provide: Provide) -> Self {
+        provide.to_string()
+    }
+}
+
+impl fmt::Display for Provide {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+
+        if let Some(version) = &self.version {
+            write!(f, "={}", version)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Check that a package name only uses the characters allowed in names
+///
+/// Colons are allowed for namespaced provides such as "so:libz.so.1". Names are also used as
+/// directory names in the package database, so they cannot start with a dot.
+pub fn validate_name(name: &str) -> Result<(), String> {
+    if name.is_empty() {
+        return Err("missing package name".to_string());
+    }
+
+    if name.starts_with('.') {
+        return Err("package name cannot start with '.'".to_string());
+    }
+
+    match name.chars().find(|c| !(c.is_ascii_alphanumeric() || "@._+-:".contains(*c))) {
+        Some(c) => Err(format!("invalid character '{}' in package name", c)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Constraint, Dependency, Operator, Provide};
+    use crate::package_info::FinalPackageInfo;
+    use crate::version::Version;
+
+    fn version(version: &str) -> Version {
+        version.parse().unwrap()
+    }
+
+    fn constraint(dependency: &str) -> Constraint {
+        dependency.parse::<Dependency>().unwrap().constraint.unwrap()
+    }
+
+    fn package(name: &str, version: &str, provides: &[&str]) -> FinalPackageInfo {
+        FinalPackageInfo {
+            provides: provides.iter().map(|provide| provide.parse().unwrap()).collect(),
+            ..FinalPackageInfo::bare(name, version)
+        }
+    }
+
+    #[test]
+    fn parse_dependency() {
+        let cases = [
+            ("zlib", "zlib", None),
+            ("openssl>=3.0.0", "openssl", Some((Operator::GreaterEqual, "3.0.0"))),
+            ("zlib=1.3.1-2", "zlib", Some((Operator::Equal, "1.3.1-2"))),
+            ("libfoo<2", "libfoo", Some((Operator::Less, "2.0.0"))),
+            ("libfoo<=2.1", "libfoo", Some((Operator::LessEqual, "2.1.0"))),
+            ("bar>1:0.1.0", "bar", Some((Operator::Greater, "1:0.1.0"))),
+            ("so:libz.so.1", "so:libz.so.1", None),
+        ];
+
+        for (input, name, expected) in cases {
+            let dependency: Dependency = input.parse().unwrap_or_else(|e| panic!("{}: {}", input, e));
+
+            assert_eq!(dependency.name, name, "{}", input);
+            assert_eq!(
+                dependency.constraint.map(|constraint| (constraint.operator, constraint.version.to_string())),
+                expected.map(|(operator, version)| (operator, version.to_string())),
+                "{}", input,
+            );
+        }
+
+        assert_eq!("zlib=1.3.1-2".parse::<Dependency>().unwrap().to_string(), "zlib=1.3.1-2");
+        assert_eq!("openssl>=3".parse::<Dependency>().unwrap().to_string(), "openssl>=3.0.0");
+    }
+
+    #[test]
+    fn parse_dependency_rejects() {
+        let cases = ["", ">=1.0.0", "openssl>=", "openssl=>3.0.0", "openssl>=3.x", "open ssl", ".hidden", "zlib==1.0.0"];
+
+        for input in cases {
+            assert!(input.parse::<Dependency>().is_err(), "{} should be rejected", input);
+        }
+    }
+
+    #[test]
+    fn parse_provide() {
+        let cases = [
+            ("sh=5.2", "sh", Some("5.2.0")),
+            ("sh", "sh", None),
+            ("so:libz.so.1", "so:libz.so.1", None),
+            ("java-runtime=1:17.0.1-3", "java-runtime", Some("1:17.0.1-3")),
+        ];
+
+        for (input, name, expected) in cases {
+            let provide: Provide = input.parse().unwrap_or_else(|e| panic!("{}: {}", input, e));
+
+            assert_eq!(provide.name, name, "{}", input);
+            assert_eq!(provide.version.map(|version| version.to_string()).as_deref(), expected, "{}", input);
+        }
+
+        for input in ["=5.2", "sh=", "sh>=5.2", "sh=5.x"] {
+            assert!(input.parse::<Provide>().is_err(), "{} should be rejected", input);
+        }
+    }
+
+    #[test]
+    fn constraint_matches() {
+        let cases = [
+            ("openssl>=3.0.0", "3.0.0", true),
+            ("openssl>=3.0.0", "3.1.2-1", true),
+            ("openssl>=3.0.0", "2.9.9", false),
+            ("openssl>=3.0.0", "3.0.0~rc1", false),
+            ("zlib=1.3.1", "1.3.1-7", true),
+            ("zlib=1.3.1-2", "1.3.1-2", true),
+            ("zlib=1.3.1-2", "1.3.1-3", false),
+            ("zlib=1.3.1-2", "1.3.1", false),
+            ("zlib>1.3.1", "1.3.1-5", false),
+            ("zlib>1.3.1-1", "1.3.1-5", true),
+            ("zlib<=1.3.1", "1.3.1-9", true),
+            ("libfoo<2", "1.9.9", true),
+            ("libfoo<2", "2.0.0", false),
+            ("libfoo<2", "2.0.0-1", false),
+            // A pre-release sorts before its release, so it satisfies an upper bound on that release
+            ("libfoo<2", "2.0.0~rc1", true),
+            ("libfoo<2", "1:1.0.0", false),
+            ("libfoo>1:0.0.0", "9.0.0", false),
+        ];
+
+        for (dependency, input, expected) in cases {
+            assert_eq!(constraint(dependency).matches(&version(input)), expected, "{} with {}", dependency, input);
+        }
+    }
+
+    #[test]
+    fn is_satisfied_by() {
+        let bash = package("bash", "5.2.26-1", &["sh=5.2", "shell"]);
+
+        let cases = [
+            ("bash", true),
+            ("bash>=5.0.0", true),
+            ("bash<5", false),
+            ("sh", true),
+            ("sh>=5.0.0", true),
+            ("sh>=6", false),
+            ("shell", true),
+            // An unversioned provide cannot satisfy a versioned dependency
+            ("shell>=1", false),
+            ("zsh", false),
+        ];
+
+        for (dependency, expected) in cases {
+            assert_eq!(dependency.parse::<Dependency>().unwrap().is_satisfied_by(&bash), expected, "{}", dependency);
+        }
+    }
+}
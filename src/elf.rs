@@ -0,0 +1,337 @@
+use std::{ffi::OsStr, fs, io::{self, Read}, path::{Path, PathBuf}, process::Command};
+
+use crate::archive::is_metadata_file;
+
+/// Kind of ELF file, only executables and shared objects are stripped and scanned for libraries
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ElfKind {
+    Executable,
+    SharedObject,
+}
+
+/// An executable or shared object of a package directory
+pub struct ElfFile {
+    /// Absolute path once installed, e.g. "/usr/bin/zig"
+    pub path: String,
+    /// Path inside the package directory
+    pub file: PathBuf,
+    pub kind: ElfKind,
+}
+
+/// Every executable and shared object of a package directory, sorted by path
+///
+/// Symlinks are left out, so each file is only listed under its real path.
+pub fn elf_files(pkg_dir: &Path) -> io::Result<Vec<ElfFile>> {
+    let mut files = Vec::new();
+
+    for entry in walkdir::WalkDir::new(pkg_dir).min_depth(1).sort_by_file_name() {
+        let entry = entry?;
+        let relative_path = entry.path().strip_prefix(pkg_dir)
+            .map_err(io::Error::other)?;
+
+        if !entry.file_type().is_file() || is_metadata_file(relative_path) {
+            continue;
+        }
+
+        if let Some(kind) = elf_kind(entry.path())? {
+            files.push(ElfFile {
+                path: format!("/{}", relative_path.display()),
+                file: entry.path().to_path_buf(),
+                kind,
+            });
+        }
+    }
+
+    Ok(files)
+}
+
+/// Read the ELF header of a file, returning None if it is not an executable or shared object
+pub fn elf_kind(path: &Path) -> io::Result<Option<ElfKind>> {
+    let mut header = [0u8; 18];
+
+    if fs::File::open(path)?.read_exact(&mut header).is_err() || header[..4] != *b"\x7fELF" {
+        return Ok(None);
+    }
+
+    // e_type, in the byte order given by EI_DATA
+    let elf_type = match header[5] {
+        1 => u16::from_le_bytes([header[16], header[17]]),
+        2 => u16::from_be_bytes([header[16], header[17]]),
+        _ => return Ok(None),
+    };
+
+    Ok(match elf_type {
+        2 => Some(ElfKind::Executable),
+        3 => Some(ElfKind::SharedObject),
+        _ => None,
+    })
+}
+
+/// Entries of the dynamic section of an ELF file
+#[derive(Default)]
+pub struct DynamicSection {
+    /// Name the library is linked against (DT_SONAME)
+    pub soname: Option<String>,
+    /// Libraries loaded along with the file (DT_NEEDED)
+    pub needed: Vec<String>,
+}
+
+/// Read the dynamic section of an ELF file, statically linked files have an empty one
+pub fn dynamic_section(path: &Path) -> io::Result<DynamicSection> {
+    let data = fs::read(path)?;
+
+    parse_dynamic_section(&data)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("{}: truncated or malformed ELF file", path.display())))
+}
+
+const PT_LOAD: u64 = 1;
+const PT_DYNAMIC: u64 = 2;
+
+const DT_NULL: u64 = 0;
+const DT_NEEDED: u64 = 1;
+const DT_STRTAB: u64 = 5;
+const DT_SONAME: u64 = 14;
+
+/// Fields of an ELF file, read in the class and byte order given by its header
+struct ElfData<'a> {
+    data: &'a [u8],
+    is_64: bool,
+    big_endian: bool,
+}
+
+impl ElfData<'_> {
+    /// Unsigned integer of size bytes at offset
+    fn uint(&self, offset: u64, size: usize) -> Option<u64> {
+        let offset = usize::try_from(offset).ok()?;
+        let bytes = self.data.get(offset..offset.checked_add(size)?)?;
+
+        Some(match self.big_endian {
+            true => bytes.iter().fold(0, |value, &byte| value << 8 | u64::from(byte)),
+            false => bytes.iter().rev().fold(0, |value, &byte| value << 8 | u64::from(byte)),
+        })
+    }
+
+    /// Address, offset or size, 4 or 8 bytes depending on the class
+    fn word(&self, offset: u64) -> Option<u64> {
+        self.uint(offset, self.word_size())
+    }
+
+    fn word_size(&self) -> usize {
+        if self.is_64 { 8 } else { 4 }
+    }
+
+    /// NUL-terminated string at offset
+    fn string(&self, offset: u64) -> Option<String> {
+        let bytes = self.data.get(usize::try_from(offset).ok()?..)?;
+        let length = bytes.iter().position(|&byte| byte == 0)?;
+
+        Some(String::from_utf8_lossy(&bytes[..length]).into_owned())
+    }
+}
+
+/// A segment of an ELF file, as described by its program header
+struct Segment {
+    kind: u64,
+    offset: u64,
+    address: u64,
+    size: u64,
+}
+
+/// Read DT_SONAME and DT_NEEDED from the PT_DYNAMIC segment, None if the file is malformed
+fn parse_dynamic_section(data: &[u8]) -> Option<DynamicSection> {
+    let elf = match (data.get(..4)?, data.get(4)?, data.get(5)?) {
+        (b"\x7fELF", 1 | 2, 1 | 2) => ElfData { data, is_64: data[4] == 2, big_endian: data[5] == 2 },
+        _ => return None,
+    };
+
+    // e_phoff, e_phentsize and e_phnum
+    let (program_headers, entry_size, count) = match elf.is_64 {
+        true => (elf.word(0x20)?, elf.uint(0x36, 2)?, elf.uint(0x38, 2)?),
+        false => (elf.word(0x1c)?, elf.uint(0x2a, 2)?, elf.uint(0x2c, 2)?),
+    };
+
+    let segments = (0..count)
+        .map(|i| {
+            let header = program_headers.checked_add(i * entry_size)?;
+
+            // p_offset, p_vaddr and p_filesz, p_flags comes before them in 64-bit files
+            let (offset, address, size) = match elf.is_64 {
+                true => (elf.word(header + 8)?, elf.word(header + 16)?, elf.word(header + 32)?),
+                false => (elf.word(header + 4)?, elf.word(header + 8)?, elf.word(header + 16)?),
+            };
+
+            Some(Segment { kind: elf.uint(header, 4)?, offset, address, size })
+        })
+        .collect::<Option<Vec<Segment>>>()?;
+
+    let mut section = DynamicSection::default();
+
+    let Some(dynamic) = segments.iter().find(|segment| segment.kind == PT_DYNAMIC) else {
+        return Some(section);
+    };
+
+    let mut needed = Vec::new();
+    let mut soname = None;
+    let mut string_table = None;
+    let entry_size = 2 * elf.word_size() as u64;
+
+    for entry in (dynamic.offset..dynamic.offset.checked_add(dynamic.size)?).step_by(entry_size as usize) {
+        let (tag, value) = (elf.word(entry)?, elf.word(entry + entry_size / 2)?);
+
+        match tag {
+            DT_NULL => break,
+            DT_NEEDED => needed.push(value),
+            DT_SONAME => soname = Some(value),
+            DT_STRTAB => string_table = Some(value),
+            _ => {}
+        }
+    }
+
+    if needed.is_empty() && soname.is_none() {
+        return Some(section);
+    }
+
+    // DT_STRTAB holds an address, found in the file through the loaded segment holding it
+    let address = string_table?;
+    let string_table = segments.iter()
+        .find(|segment| segment.kind == PT_LOAD && (segment.address..segment.address.saturating_add(segment.size)).contains(&address))
+        .map(|segment| address - segment.address + segment.offset)?;
+
+    for offset in needed {
+        section.needed.push(elf.string(string_table.checked_add(offset)?)?);
+    }
+
+    if let Some(offset) = soname {
+        section.soname = Some(elf.string(string_table.checked_add(offset)?)?);
+    }
+
+    Some(section)
+}
+
+/// Run one of the binutils, returning its output
+pub fn run_tool<'a>(program: &str, args: impl IntoIterator<Item = &'a OsStr>) -> io::Result<String> {
+    let output = Command::new(program).args(args).output()
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", program, e)))?;
+
+    if !output.status.success() {
+        return Err(io::Error::other(format!("{} failed ({}): {}", program, output.status, String::from_utf8_lossy(&output.stderr).trim())));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::fs;
+
+    use super::{dynamic_section, elf_kind, ElfKind};
+
+    /// Build an ELF file of the given kind, class and byte order with a dynamic section naming
+    /// soname and needed, its string table loaded at an address other than its offset
+    pub fn elf_file(kind: ElfKind, is_64: bool, big_endian: bool, soname: Option<&str>, needed: &[&str]) -> Vec<u8> {
+        let word = if is_64 { 8 } else { 4 };
+        let (header_size, program_header_size) = if is_64 { (64, 56) } else { (52, 32) };
+        let base = 0x400000;
+
+        let mut strings = vec![0u8];
+        let mut string_offset = |string: &str| {
+            let offset = strings.len() as u64;
+            strings.extend(string.as_bytes());
+            strings.push(0);
+            offset
+        };
+
+        let mut entries: Vec<(u64, u64)> = needed.iter().map(|library| (1, string_offset(library))).collect();
+        entries.extend(soname.map(|soname| (14, string_offset(soname))));
+
+        let strings_offset = header_size + 2 * program_header_size;
+        let dynamic_offset = (strings_offset + strings.len()).next_multiple_of(word);
+        entries.extend([(5, (base + strings_offset) as u64), (0, 0)]);
+
+        let dynamic_size = entries.len() * 2 * word;
+        let mut data = vec![0u8; dynamic_offset + dynamic_size];
+
+        let put = |data: &mut Vec<u8>, offset: usize, size: usize, value: u64| {
+            let bytes = &value.to_le_bytes()[..size];
+
+            for (i, &byte) in bytes.iter().enumerate() {
+                data[if big_endian { offset + size - 1 - i } else { offset + i }] = byte;
+            }
+        };
+
+        data[..4].copy_from_slice(b"\x7fELF");
+        data[4] = if is_64 { 2 } else { 1 };
+        data[5] = if big_endian { 2 } else { 1 };
+        data[6] = 1;
+
+        put(&mut data, 16, 2, if kind == ElfKind::Executable { 2 } else { 3 });
+
+        let (phoff, phentsize, phnum) = if is_64 { (0x20, 0x36, 0x38) } else { (0x1c, 0x2a, 0x2c) };
+        put(&mut data, phoff, word, header_size as u64);
+        put(&mut data, phentsize, 2, program_header_size as u64);
+        put(&mut data, phnum, 2, 2);
+
+        // PT_LOAD of the whole file, then PT_DYNAMIC
+        let segments = [(1, 0, base, data.len()), (2, dynamic_offset, base + dynamic_offset, dynamic_size)];
+
+        for (i, (kind, offset, address, size)) in segments.into_iter().enumerate() {
+            let header = header_size + i * program_header_size;
+            let (offset_at, address_at, size_at) = if is_64 { (8, 16, 32) } else { (4, 8, 16) };
+
+            put(&mut data, header, 4, kind);
+            put(&mut data, header + offset_at, word, offset as u64);
+            put(&mut data, header + address_at, word, address as u64);
+
+            // p_filesz, then p_memsz
+            put(&mut data, header + size_at, word, size as u64);
+            put(&mut data, header + size_at + word, word, size as u64);
+        }
+
+        data[strings_offset..strings_offset + strings.len()].copy_from_slice(&strings);
+
+        for (i, (tag, value)) in entries.into_iter().enumerate() {
+            put(&mut data, dynamic_offset + i * 2 * word, word, tag);
+            put(&mut data, dynamic_offset + (i * 2 + 1) * word, word, value);
+        }
+
+        data
+    }
+
+    #[test]
+    fn read_dynamic_section() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("elf");
+
+        for (is_64, big_endian) in [(true, false), (true, true), (false, false), (false, true)] {
+            let cases = [
+                (ElfKind::SharedObject, Some("libfoo.so.1"), vec!["libbar.so.2", "libc.so.6"]),
+                (ElfKind::Executable, None, vec!["libfoo.so.1"]),
+                (ElfKind::SharedObject, Some("libbaz.so"), vec![]),
+                (ElfKind::Executable, None, vec![]),
+            ];
+
+            for (kind, soname, needed) in cases {
+                fs::write(&path, elf_file(kind, is_64, big_endian, soname, &needed)).unwrap();
+
+                let section = dynamic_section(&path).unwrap();
+                let description = format!("64-bit {} big-endian {} {:?}", is_64, big_endian, soname);
+
+                assert!(elf_kind(&path).unwrap() == Some(kind), "{}", description);
+                assert_eq!(section.soname.as_deref(), soname, "{}", description);
+                assert_eq!(section.needed, needed, "{}", description);
+            }
+        }
+    }
+
+    #[test]
+    fn reject_malformed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("elf");
+        let valid = elf_file(ElfKind::Executable, true, false, None, &["libfoo.so.1"]);
+
+        for length in [3, 20, 100, valid.len() - 30] {
+            fs::write(&path, &valid[..length]).unwrap();
+            assert!(dynamic_section(&path).is_err(), "{} bytes", length);
+        }
+    }
+}
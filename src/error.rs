@@ -0,0 +1,127 @@
+use std::io;
+use thiserror::Error;
+
+use crate::config::ConfigError;
+use crate::path_utils::PathError;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Every failure vrdpkg can report
+///
+/// Errors raised inside Lua callbacks are turned into Lua errors carrying the script location
+/// (see `lua_functions::script_error`), everything else reaches `main` and selects the exit code.
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Path error: {0}")]
+    Path(#[from] PathError),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error("Git error: {0}")]
+    Git(#[from] git2::Error),
+    #[error("Download error: {0}")]
+    Download(#[from] reqwest::Error),
+    #[error("Invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("{0}")]
+    Script(#[from] mlua::Error),
+    #[error("Invalid regex: {0}")]
+    Regex(#[from] regex::Error),
+    #[error("{phase} phase failed: {source}")]
+    Phase { phase: String, source: mlua::Error },
+    #[error("Invalid package: {0}")]
+    InvalidPackage(String),
+    #[error("Invalid version \"{0}\": {1}")]
+    InvalidVersion(String, String),
+    #[error("Invalid dependency \"{0}\": {1}")]
+    InvalidDependency(String, String),
+    #[error("Failed to create package archive: {0}")]
+    Archive(io::Error),
+    #[error("Package is not reproducible")]
+    NotReproducible,
+    #[error("Invalid manifest: {0}")]
+    Manifest(String),
+    #[error("Verification failed, {0} problem(s) found")]
+    VerificationFailed(usize),
+    #[error("{0} is already installed")]
+    AlreadyInstalled(String),
+    #[error("Package {0} is not installed")]
+    NotInstalled(String),
+    #[error("No installed package owns {0}")]
+    NotOwned(String),
+    #[error("Conflicts found:\n  {}", .0.join("\n  "))]
+    Conflicts(Vec<String>),
+    #[error("Removing would break installed packages:\n  {}", .0.join("\n  "))]
+    RequiredBy(Vec<String>),
+    #[error("Cannot resolve dependencies: {0}")]
+    Unresolvable(String),
+    #[error("Repository error: {0}")]
+    Repository(String),
+    #[error("{hook} of {package} failed: {source}")]
+    Scriptlet { package: String, hook: String, source: mlua::Error },
+    #[error("Another vrdpkg process is changing {0:?}")]
+    Locked(std::path::PathBuf),
+    #[error("Package checks failed:\n  {}", .0.join("\n  "))]
+    QaFailed(Vec<String>),
+    #[error("Lint failed, {0} error(s) found")]
+    LintFailed(usize),
+    #[error("Checksum mismatch for {filename} downloaded from {url}:\n  expected {expected}\n  got      {actual}")]
+    ChecksumMismatch { filename: String, url: String, expected: String, actual: String },
+}
+
+impl Error {
+    /// Process exit code for this class of failure
+    ///
+    /// | Code | Failure                                              |
+    /// |------|------------------------------------------------------|
+    /// | 1    | I/O error                                            |
+    /// | 2    | Invalid command line (reported by clap)              |
+    /// | 3    | Invalid configuration file                           |
+    /// | 4    | Error raised by the build script or one of its phases |
+    /// | 5    | Invalid package metadata (INFO, version, dependencies, arch) |
+    /// | 6    | Path outside of the allowed directories              |
+    /// | 7    | Git error                                            |
+    /// | 8    | Download error                                       |
+    /// | 9    | Invalid JSON                                         |
+    /// | 10   | Package archive could not be written                 |
+    /// | 11   | Package is not reproducible                          |
+    /// | 12   | Invalid package manifest                             |
+    /// | 13   | Files do not match the package manifest              |
+    /// | 14   | Package conflicts with the installed packages or files |
+    /// | 15   | Package or file not found among installed packages   |
+    /// | 16   | Package is required by other installed packages      |
+    /// | 17   | Dependencies cannot be resolved                      |
+    /// | 18   | Invalid repository or repository index               |
+    /// | 19   | Root is locked by another vrdpkg process             |
+    /// | 20   | Install scriptlet of a package failed                |
+    /// | 21   | Package directory failed a check set to error        |
+    /// | 22   | Build script failed the lint                         |
+    /// | 23   | Downloaded source does not match its checksum        |
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Io(_) => 1,
+            Error::Config(_) => 3,
+            Error::Script(_) | Error::Regex(_) | Error::Phase { .. } => 4,
+            Error::InvalidPackage(_) | Error::InvalidVersion(..) | Error::InvalidDependency(..) => 5,
+            Error::Path(_) => 6,
+            Error::Git(_) => 7,
+            Error::Download(_) => 8,
+            Error::Json(_) => 9,
+            Error::Archive(_) => 10,
+            Error::NotReproducible => 11,
+            Error::Manifest(_) => 12,
+            Error::VerificationFailed(_) => 13,
+            Error::AlreadyInstalled(_) | Error::Conflicts(_) => 14,
+            Error::NotInstalled(_) | Error::NotOwned(_) => 15,
+            Error::RequiredBy(_) => 16,
+            Error::Unresolvable(_) => 17,
+            Error::Repository(_) => 18,
+            Error::Locked(_) => 19,
+            Error::Scriptlet { .. } => 20,
+            Error::QaFailed(_) => 21,
+            Error::LintFailed(_) => 22,
+            Error::ChecksumMismatch { .. } => 23,
+        }
+    }
+}
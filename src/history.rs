@@ -0,0 +1,89 @@
+use std::{fs, io::Write, time::{SystemTime, UNIX_EPOCH}};
+
+use crate::database::Database;
+use crate::error::Result;
+
+/// Name of the history log, in the database directory
+pub const HISTORY_FILE: &str = "history.log";
+
+/// Append a line describing a change to the installed packages to the history log
+///
+/// Lines look like "[2025-01-31T12:00:00Z] removed hello (1.0.0-1)".
+pub fn record(database: &Database, message: &str) -> Result<()> {
+    fs::create_dir_all(database.dir())?;
+
+    let mut log = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(database.dir().join(HISTORY_FILE))?;
+
+    writeln!(log, "[{}] {}", timestamp(), message)?;
+
+    Ok(())
+}
+
+/// Current UTC time formatted as RFC 3339
+fn timestamp() -> String {
+    format_timestamp(SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0))
+}
+
+/// Format seconds since the epoch as an RFC 3339 UTC time, e.g. "2025-01-31T12:00:00Z"
+fn format_timestamp(seconds: u64) -> String {
+    let (days, time) = (seconds / 86400, seconds % 86400);
+    let (year, month, day) = civil_from_days(days);
+
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, time / 3600, time % 3600 / 60, time % 60)
+}
+
+/// Convert days since the epoch to a (year, month, day) civil date, Howard Hinnant's
+/// civil_from_days algorithm
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    // Count from 0000-03-01 so leap days fall at the end of the year
+    let z = days + 719468;
+    let era = z / 146097;
+    let day_of_era = z % 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{civil_from_days, format_timestamp};
+
+    #[test]
+    fn civil_dates() {
+        let cases = [
+            (0, (1970, 1, 1)),
+            (59, (1970, 3, 1)),
+            (10956, (1999, 12, 31)),
+            (11016, (2000, 2, 29)),
+            (11017, (2000, 3, 1)),
+            (20818, (2026, 12, 31)),
+            (47541, (2100, 3, 1)),
+        ];
+
+        for (days, date) in cases {
+            assert_eq!(civil_from_days(days), date, "{}", days);
+        }
+    }
+
+    #[test]
+    fn timestamps() {
+        let cases = [
+            (0, "1970-01-01T00:00:00Z"),
+            (951782400, "2000-02-29T00:00:00Z"),
+            (1798761599, "2026-12-31T23:59:59Z"),
+            (4107587405, "2100-03-01T12:30:05Z"),
+        ];
+
+        for (seconds, timestamp) in cases {
+            assert_eq!(format_timestamp(seconds), timestamp, "{}", seconds);
+        }
+    }
+}
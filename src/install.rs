@@ -0,0 +1,296 @@
+use std::{collections::{HashMap, HashSet}, fs, path::{Path, PathBuf}};
+
+use crate::database::{file_owners, Database, InstalledPackage};
+use crate::dependency::Dependency;
+use crate::error::{Error, Result};
+use crate::file_operations::sha256sum_file;
+use crate::manifest::EntryKind;
+use crate::package_info::PackageArchive;
+use crate::path_utils::resolve_in_root;
+use crate::resolver::{supersedes, Resolver};
+use crate::sync::{fetch_package, SyncPackage};
+use crate::transaction::Transaction;
+
+/// Install package archives and packages from the synced repositories under root, dependencies first
+///
+/// Every dependency must be satisfied by an installed package, one of the archives or a package
+/// of the repositories. Repository packages are all downloaded and checked before anything is
+/// installed, then every package is installed in a single transaction.
+pub fn install_packages(root: &Path, archive_paths: &[PathBuf], names: &[Dependency], repositories: &[SyncPackage]) -> Result<()> {
+    let archives = archive_paths.iter()
+        .map(|path| PackageArchive::open(path))
+        .collect::<Result<Vec<PackageArchive>>>()?;
+
+    let installed = Database::open(root).packages()?;
+
+    let mut requests: Vec<Dependency> = archives.iter()
+        .map(|package| Dependency::exact(&package.info.name, &package.info.version))
+        .collect();
+
+    for name in names {
+        match installed.iter().find(|package| package.info.name == name.name && name.is_satisfied_by(&package.info)) {
+            Some(package) => println!("{} is already installed, skipping", package.info.display_name()),
+            None => requests.push(name.clone()),
+        }
+    }
+
+    if requests.is_empty() {
+        return Ok(());
+    }
+
+    let available = archives.iter().map(|package| &package.info)
+        .chain(repositories.iter().map(|package| &package.entry.info));
+
+    let plan = Resolver::new(available, installed.iter().map(|package| &package.info))
+        .resolve(&requests)?;
+
+    let mut fetched = HashMap::new();
+
+    for &index in &plan {
+        if let Some(sync_package) = index.checked_sub(archives.len()).map(|index| &repositories[index]) {
+            fetched.insert(index, open_sync_package(root, sync_package)?);
+        }
+    }
+
+    Transaction::run(root, |transaction| {
+        // Repository packages may upgrade an installed package to satisfy a versioned dependency
+        for index in plan {
+            match fetched.get(&index) {
+                Some(package) => install_package(transaction, package, true)?,
+                None => install_package(transaction, &archives[index], false)?,
+            }
+        }
+
+        Ok(())
+    })
+}
+
+/// Download a package of a repository and check that it holds what the index says
+pub fn open_sync_package(root: &Path, sync_package: &SyncPackage) -> Result<PackageArchive> {
+    let package = PackageArchive::open(&fetch_package(root, sync_package)?)?;
+
+    if package.info.name != sync_package.entry.info.name || package.info.version != sync_package.entry.info.version {
+        return Err(Error::Repository(format!("{} does not contain {}", sync_package.entry.filename, sync_package.entry.info.display_name())));
+    }
+
+    Ok(package)
+}
+
+/// Stage the installation of a package archive in a transaction
+///
+/// Nothing is staged if the package conflicts with an installed package or would overwrite a
+/// file it does not own, unless the owner is one of the packages it replaces. Replaced packages
+/// are removed along with it. When upgrading, the installed package with the same name is
+/// replaced the same way, so files only the old version shipped are removed. The install
+/// scriptlets of the packages run around the change.
+pub fn install_package(transaction: &mut Transaction, package: &PackageArchive, upgrade: bool) -> Result<()> {
+    let info = &package.info;
+
+    if !info.arch.iter().any(|arch| arch == std::env::consts::ARCH) {
+        return Err(Error::InvalidPackage(format!("{} is not available for host architecture {}", info.display_name(), std::env::consts::ARCH)));
+    }
+
+    if !upgrade && let Some(installed) = transaction.installed().iter().find(|installed| installed.info.name == info.name) {
+        return Err(Error::AlreadyInstalled(installed.info.display_name()));
+    }
+
+    let problems = package.manifest.verify_archive(&package.path)?;
+
+    if !problems.is_empty() {
+        for problem in &problems {
+            eprintln!("{}", problem);
+        }
+
+        return Err(Error::VerificationFailed(problems.len()));
+    }
+
+    let installed = transaction.installed().to_vec();
+    let (replaced, others): (Vec<&InstalledPackage>, Vec<&InstalledPackage>) = installed.iter()
+        .partition(|other| supersedes(info, &other.info));
+
+    let conflicts = find_conflicts(transaction, package, &installed, &replaced, &others)?;
+
+    if !conflicts.is_empty() {
+        return Err(Error::Conflicts(conflicts));
+    }
+
+    let previous = replaced.iter().find(|old| old.info.name == info.name);
+
+    match previous {
+        Some(old) => println!("Upgrading {} {} -> {}", info.name, old.info.version, info.version),
+        None => println!("Installing {}", info.display_name()),
+    }
+
+    let version = info.version.to_string();
+
+    for old in replaced.iter().filter(|old| old.info.name != info.name) {
+        transaction.run_hook(&old.info.name, old.install.as_deref(), "PRE_REMOVE", &[old.info.version.to_string()])?;
+    }
+
+    if previous.is_none() {
+        transaction.run_hook(&info.name, package.install.as_deref(), "PRE_INSTALL", std::slice::from_ref(&version))?;
+    }
+
+    let redirects = protected_files(transaction.root(), package, &replaced)?;
+    transaction.stage_archive(package, &redirects)?;
+
+    for old in &replaced {
+        transaction.remove_package(&old.info.name)?;
+
+        if old.info.name != info.name {
+            println!("Replacing {}", old.info.display_name());
+            transaction.queue_hook(&old.info.name, old.install.as_deref(), "POST_REMOVE", &[old.info.version.to_string()]);
+            transaction.record(format!("replaced {} ({}) with {} ({})", old.info.name, old.info.version, info.name, info.version))?;
+        }
+    }
+
+    transaction.add_package(package)?;
+
+    // Files of the new package are still owned, only what the replaced packages alone shipped goes
+    for old in &replaced {
+        remove_files(transaction, old)?;
+    }
+
+    match previous {
+        Some(old) => {
+            transaction.queue_hook(&info.name, package.install.as_deref(), "POST_UPGRADE", &[version, old.info.version.to_string()]);
+            transaction.record(format!("upgraded {} ({} -> {})", info.name, old.info.version, info.version))
+        }
+        None => {
+            transaction.queue_hook(&info.name, package.install.as_deref(), "POST_INSTALL", &[version]);
+            transaction.record(format!("installed {} ({})", info.name, info.version))
+        }
+    }
+}
+
+/// Describe every reason the package cannot be installed next to the installed packages
+///
+/// Files the transaction already removes do not conflict.
+fn find_conflicts(transaction: &Transaction, package: &PackageArchive, installed: &[InstalledPackage], replaced: &[&InstalledPackage], others: &[&InstalledPackage]) -> Result<Vec<String>> {
+    let root = transaction.root();
+    let info = &package.info;
+    let mut conflicts = Vec::new();
+
+    for other in others {
+        if info.conflicts.iter().any(|dependency| dependency.is_satisfied_by(&other.info))
+            || other.info.conflicts.iter().any(|dependency| dependency.is_satisfied_by(info))
+        {
+            conflicts.push(format!("{} conflicts with installed package {}", info.display_name(), other.info.display_name()));
+        }
+    }
+
+    let owners = file_owners(installed);
+    let replaced_names: HashSet<&str> = replaced.iter().map(|old| old.info.name.as_str()).collect();
+
+    for entry in &package.manifest.entries {
+        let target = install_path(root, &entry.path)?;
+
+        match owners.get(entry.path.as_str()) {
+            Some(owner) if replaced_names.contains(owner) => {}
+            Some(owner) => conflicts.push(format!("{} is owned by {}", entry.path, owner)),
+            None if entry.kind == EntryKind::Dir => {
+                if fs::symlink_metadata(&target).is_ok() && !resolve_in_root(root, Path::new(&entry.path))?.is_dir() {
+                    conflicts.push(format!("{} exists in the filesystem and is not a directory", entry.path));
+                }
+            }
+            None => {
+                if fs::symlink_metadata(&target).is_ok() && !transaction.is_removed(&target) {
+                    conflicts.push(format!("{} exists in the filesystem", entry.path));
+                }
+            }
+        }
+    }
+
+    Ok(conflicts)
+}
+
+/// Decide where the backup files of a package go when a file is already installed at their path
+///
+/// A file the user edited is kept and the packaged version is installed next to it as
+/// `.vrdnew`, unless the package did not change it. Unedited files are replaced.
+fn protected_files<'a>(root: &Path, package: &'a PackageArchive, replaced: &[&InstalledPackage]) -> Result<HashMap<&'a str, Option<PathBuf>>> {
+    let mut redirects = HashMap::new();
+
+    for file in &package.info.backup {
+        let target = install_path(root, &file.path)?;
+
+        if !fs::symlink_metadata(&target).is_ok_and(|metadata| metadata.is_file()) {
+            continue;
+        }
+
+        let current = sha256sum_file(&target)?;
+
+        // Hash of the file as the replaced package shipped it
+        let original = replaced.iter().find_map(|old| {
+            old.info.backup.iter()
+                .find(|old_file| old_file.path == file.path)
+                .map(|old_file| old_file.sha256.clone())
+                .or_else(|| old.manifest.entries.iter().find(|entry| entry.path == file.path).and_then(|entry| entry.sha256.clone()))
+        });
+
+        if current == file.sha256 || original.as_ref() == Some(&current) {
+            continue;
+        }
+
+        if original.as_ref() == Some(&file.sha256) {
+            redirects.insert(file.path.as_str(), None);
+        } else {
+            let new_path = PathBuf::from(format!("{}.vrdnew", target.display()));
+            eprintln!("Warning: {} was modified, the new version is installed as {}.vrdnew", file.path, file.path);
+            redirects.insert(file.path.as_str(), Some(new_path));
+        }
+    }
+
+    Ok(redirects)
+}
+
+/// Stage the removal of the files, symlinks and empty directories of an installed package
+///
+/// Paths listed by a package that stays installed once the transaction commits are kept. Backup
+/// files the user edited are saved as `.vrdsave` instead of being deleted.
+pub fn remove_files(transaction: &mut Transaction, package: &InstalledPackage) -> Result<()> {
+    let manifest = &package.manifest;
+    let keep: HashSet<String> = transaction.installed().iter()
+        .flat_map(|package| package.manifest.entries.iter())
+        .map(|entry| entry.path.clone())
+        .collect();
+
+    // Children are listed after their parent, going backwards empties directories before they are pruned
+    for entry in manifest.entries.iter().rev() {
+        if keep.contains(&entry.path) {
+            continue;
+        }
+
+        let target = install_path(transaction.root(), &entry.path)?;
+
+        if let Some(file) = package.info.backup.iter().find(|file| file.path == entry.path)
+            && target.is_file() && !target.is_symlink()
+            && sha256sum_file(&target)? != file.sha256
+        {
+            eprintln!("Warning: {} was modified, it is saved as {}.vrdsave", entry.path, entry.path);
+            transaction.rename_file(target.clone(), PathBuf::from(format!("{}.vrdsave", target.display())));
+            continue;
+        }
+
+        match entry.kind {
+            EntryKind::File | EntryKind::Link => transaction.remove_file(target),
+            EntryKind::Dir => transaction.prune_dir(target),
+        }
+    }
+
+    Ok(())
+}
+
+/// Location of a package path under root, following the symlinks of its parent directories
+/// inside root
+pub fn install_path(root: &Path, path: &str) -> Result<PathBuf> {
+    let path = Path::new(path);
+
+    let Some(name) = path.file_name() else {
+        return Err(Error::InvalidPackage(format!("invalid path {:?}", path)));
+    };
+
+    let parent = resolve_in_root(root, path.parent().unwrap_or(Path::new("/")))?;
+
+    Ok(parent.join(name))
+}
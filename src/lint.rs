@@ -0,0 +1,543 @@
+use std::{collections::{BTreeSet, HashSet}, path::Path};
+use mlua::{Lua, Table, Value};
+
+use crate::build::{function_exists, load_build_script, package_function_name};
+use crate::error::{Error, Result};
+use crate::package_info::{info_table, lua_get_package_info, lua_get_subpackages, FieldType, PackageInfo, INFO_FIELDS};
+use crate::scriptlet::HOOKS;
+use crate::spdx::check_license_expression;
+use crate::version::Version;
+
+/// Values of ARCH on the architectures Rust supports on Linux
+pub const KNOWN_ARCHES: [&str; 18] = [
+    "x86", "x86_64", "arm", "aarch64", "csky", "hexagon", "loongarch64", "m68k", "mips", "mips32r6",
+    "mips64", "mips64r6", "powerpc", "powerpc64", "riscv32", "riscv64", "s390x", "sparc64",
+];
+
+/// Phases a build script must define, the other ones are optional
+const REQUIRED_PHASES: [&str; 3] = ["SOURCES", "PREPARE", "PACKAGE"];
+
+const OPTIONAL_PHASES: [&str; 3] = ["VERSION", "BUILD", "CHECK"];
+
+const LUA_KEYWORDS: [&str; 22] = [
+    "and", "break", "do", "else", "elseif", "end", "false", "for", "function", "goto", "if", "in",
+    "local", "nil", "not", "or", "repeat", "return", "then", "true", "until", "while",
+];
+
+/// Problems found in a build script
+#[derive(Default)]
+struct Lint {
+    errors: Vec<String>,
+    warnings: Vec<String>,
+}
+
+/// Check a build script without running any of its phases
+///
+/// Only the top level of the script runs, so INFO and the phase functions can be inspected.
+/// Fails if an error is found, warnings are only printed.
+pub fn lint_project(buildpkg_lua: &Path) -> Result<()> {
+    let working_dir = buildpkg_lua.parent().unwrap_or(Path::new("/"));
+
+    let lua = Lua::new();
+    let (lua_code, chunk_name) = load_build_script(&lua, buildpkg_lua, &working_dir.join("src"), &working_dir.join("pkg"))?;
+
+    let mut lint = Lint::default();
+
+    check_info_fields(&lua, &mut lint)?;
+
+    // The parsed packages are only checked once every field has the right type
+    if lint.errors.is_empty() {
+        match parse_packages(&lua) {
+            Ok(packages) => check_packages(&lua, &packages, &mut lint),
+            Err(Error::InvalidPackage(message)) => lint.errors.push(message),
+            Err(e) => return Err(e),
+        }
+    }
+
+    check_phases(&lua, &mut lint)?;
+    check_calls(&lua, &lua_code, &mut lint)?;
+
+    for warning in &lint.warnings {
+        println!("{}: warning: {}", chunk_name, warning);
+    }
+
+    for error in &lint.errors {
+        println!("{}: error: {}", chunk_name, error);
+    }
+
+    if !lint.errors.is_empty() {
+        return Err(Error::LintFailed(lint.errors.len()));
+    }
+
+    match lint.warnings.len() {
+        0 => println!("No problems found in {}", chunk_name),
+        count => println!("{} warning(s) found in {}", count, chunk_name),
+    }
+
+    Ok(())
+}
+
+/// Check that INFO holds every required field with the right type, and nothing else
+fn check_info_fields(lua: &Lua, lint: &mut Lint) -> Result<()> {
+    let info = match info_table(lua) {
+        Ok(info) => info,
+        Err(Error::InvalidPackage(message)) => {
+            lint.errors.push(message);
+            return Ok(());
+        }
+        Err(e) => return Err(e),
+    };
+
+    for (field, field_type, required) in INFO_FIELDS {
+        let value: Value = info.get(field)?;
+
+        let valid = match (&value, field_type) {
+            (Value::Nil, _) => {
+                if required {
+                    lint.errors.push(format!("INFO: {} field missing", field));
+                }
+
+                continue;
+            }
+            (Value::String(_), FieldType::String) => true,
+            (Value::Boolean(_), FieldType::Boolean) => true,
+            (Value::Table(table), FieldType::List) => table.clone().sequence_values::<Value>()
+                .all(|value| matches!(value, Ok(Value::String(_)))),
+            (Value::Table(table), FieldType::TableList) => table.clone().sequence_values::<Value>()
+                .all(|value| matches!(value, Ok(Value::Table(_)))),
+            (Value::Table(_), FieldType::Table) => true,
+            _ => false,
+        };
+
+        if !valid {
+            let expected = match field_type {
+                FieldType::String => "a string",
+                FieldType::Boolean => "a boolean",
+                FieldType::List => "a list of strings",
+                FieldType::TableList => "a list of tables",
+                FieldType::Table => "a table",
+            };
+
+            lint.errors.push(format!("INFO: {} field must be {}", field, expected));
+        }
+    }
+
+    let mut unknown_fields = BTreeSet::new();
+
+    for pair in info.pairs::<Value, Value>() {
+        match pair?.0 {
+            Value::String(key) => {
+                let key = key.to_string_lossy();
+
+                if !INFO_FIELDS.iter().any(|(field, _, _)| key == *field) {
+                    unknown_fields.insert(key);
+                }
+            }
+            _ => {
+                unknown_fields.insert("(unnamed)".to_string());
+            }
+        }
+    }
+
+    lint.warnings.extend(unknown_fields.into_iter().map(|field| format!("INFO: unknown field {}", field)));
+
+    Ok(())
+}
+
+/// Parse INFO and its subpackages the way a build does
+fn parse_packages(lua: &Lua) -> Result<Vec<PackageInfo>> {
+    let package_info = lua_get_package_info(lua)?;
+    let mut packages = lua_get_subpackages(lua, &package_info)?;
+
+    packages.insert(0, package_info);
+
+    Ok(packages)
+}
+
+/// Check the version, arch and license of the parsed packages, and the functions they need
+fn check_packages(lua: &Lua, packages: &[PackageInfo], lint: &mut Lint) {
+    let version_function_exists = function_exists(lua, "VERSION");
+
+    match &packages[0].version {
+        Some(_) if version_function_exists => lint.errors.push("version field and VERSION function both found".to_string()),
+        Some(version) => {
+            if let Err(e) = version.parse::<Version>() {
+                lint.errors.push(format!("INFO: version field: {}", e));
+            }
+        }
+        None if !version_function_exists => lint.errors.push("version field missing and VERSION function not found".to_string()),
+        None => {}
+    }
+
+    for package in packages {
+        for arch in &package.arch {
+            if !KNOWN_ARCHES.contains(&arch.as_str()) {
+                lint.warnings.push(format!("{}: arch field: {} is not an architecture name (expected one of {})", package.name, arch, KNOWN_ARCHES.join(", ")));
+            }
+        }
+
+        match check_license_expression(&package.license) {
+            Ok(warnings) => lint.warnings.extend(warnings.into_iter().map(|warning| format!("{}: license field: {}", package.name, warning))),
+            Err(e) => lint.errors.push(format!("{}: license field is not an SPDX expression: {}", package.name, e)),
+        }
+    }
+
+    for subpackage in &packages[1..] {
+        let function_name = package_function_name(&subpackage.name);
+
+        if !function_exists(lua, &function_name) {
+            lint.errors.push(format!("{} function not found for subpackage {}", function_name, subpackage.name));
+        }
+    }
+}
+
+/// Check that the required phases are defined and that no other uppercase function looks like one
+fn check_phases(lua: &Lua, lint: &mut Lint) -> Result<()> {
+    let sources_declared = info_table(lua).ok()
+        .is_some_and(|info| matches!(info.get::<Value>("sources"), Ok(Value::Table(_))));
+
+    for phase in REQUIRED_PHASES {
+        // Declared sources can replace the SOURCES phase
+        if phase == "SOURCES" && sources_declared {
+            continue;
+        }
+
+        if !function_exists(lua, phase) {
+            lint.errors.push(format!("{} phase not defined", phase));
+        }
+    }
+
+    let subpackage_functions: Vec<String> = info_table(lua).ok()
+        .and_then(|info| info.get::<Option<Table>>("subpackages").ok().flatten())
+        .map(|subpackages| subpackages.sequence_values::<Table>()
+            .filter_map(|subpackage| subpackage.ok()?.get::<String>("name").ok())
+            .map(|name| package_function_name(&name))
+            .collect())
+        .unwrap_or_default();
+
+    let mut functions = BTreeSet::new();
+
+    for pair in lua.globals().pairs::<Value, Value>() {
+        if let (Value::String(global), Value::Function(_)) = pair? {
+            functions.insert(global.to_string_lossy());
+        }
+    }
+
+    for global in functions {
+        if HOOKS.contains(&global.as_str()) {
+            lint.errors.push(format!("{} is an install hook, define it in the file named by the install field", global));
+            continue;
+        }
+
+        let known = REQUIRED_PHASES.contains(&global.as_str())
+            || OPTIONAL_PHASES.contains(&global.as_str())
+            || subpackage_functions.contains(&global);
+
+        if !known && global.chars().all(|c| c.is_ascii_uppercase() || c == '_') {
+            lint.warnings.push(format!("{} is neither a phase nor an install hook and is never called", global));
+        }
+    }
+
+    Ok(())
+}
+
+/// Warn about calls to global functions neither vrdpkg, Lua nor the script define
+fn check_calls(lua: &Lua, lua_code: &str, lint: &mut Lint) -> Result<()> {
+    let tokens = tokenize(lua_code);
+
+    let mut defined: HashSet<String> = HashSet::new();
+
+    for pair in lua.globals().pairs::<Value, Value>() {
+        if let (Value::String(global), _) = pair? {
+            defined.insert(global.to_string_lossy());
+        }
+    }
+
+    defined.extend(declared_names(&tokens).into_iter().map(str::to_string));
+
+    let mut reported = BTreeSet::new();
+
+    for (i, (token, line)) in tokens.iter().enumerate() {
+        let Token::Name(name) = token else {
+            continue;
+        };
+
+        let is_call = matches!(tokens.get(i + 1), Some((Token::Punct("(" | "{") | Token::Str, _)));
+        let is_field = i > 0 && matches!(tokens[i - 1].0, Token::Punct("." | ":") | Token::Name("function"));
+
+        if is_call && !is_field && !LUA_KEYWORDS.contains(name) && !defined.contains(*name) && reported.insert((*line, *name)) {
+            lint.warnings.push(format!("line {}: call to {}, which is not a function vrdpkg provides", line, name));
+        }
+    }
+
+    Ok(())
+}
+
+/// Names the script declares: locals, function parameters, loop variables and assigned globals
+fn declared_names<'a>(tokens: &[(Token<'a>, usize)]) -> HashSet<&'a str> {
+    let mut names = HashSet::new();
+    let name_at = |i: usize| match tokens.get(i) {
+        Some((Token::Name(name), _)) if !LUA_KEYWORDS.contains(name) => Some(*name),
+        _ => None,
+    };
+
+    for (i, (token, _)) in tokens.iter().enumerate() {
+        match token {
+            // local a, b <const> / for k, v in
+            Token::Name("local" | "for") => {
+                let mut j = i + 1;
+
+                while let Some(name) = name_at(j) {
+                    names.insert(name);
+
+                    while matches!(tokens.get(j + 1), Some((Token::Punct("<" | ">"), _)) | Some((Token::Name("const" | "close"), _))) {
+                        j += 1;
+                    }
+
+                    if !matches!(tokens.get(j + 1), Some((Token::Punct(","), _))) {
+                        break;
+                    }
+
+                    j += 2;
+                }
+            }
+            // function name(a, b) / function(a, b)
+            Token::Name("function") => {
+                if let Some(name) = name_at(i + 1)
+                    && !matches!(tokens.get(i + 2), Some((Token::Punct("." | ":"), _)))
+                {
+                    names.insert(name);
+                }
+
+                let parameters = tokens[i..].iter()
+                    .skip_while(|(token, _)| *token != Token::Punct("("))
+                    .skip(1)
+                    .take_while(|(token, _)| *token != Token::Punct(")"));
+
+                names.extend(parameters.filter_map(|(token, _)| match token {
+                    Token::Name(name) => Some(*name),
+                    _ => None,
+                }));
+            }
+            // name = value, not preceded by a field access
+            Token::Name(name) => {
+                let assigned = matches!(tokens.get(i + 1), Some((Token::Punct("="), _)));
+                let is_field = i > 0 && matches!(tokens[i - 1].0, Token::Punct("." | ":"));
+
+                if assigned && !is_field {
+                    names.insert(*name);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    names
+}
+
+/// A token of a Lua script, only names and punctuation matter to the lint
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Token<'a> {
+    Name(&'a str),
+    Punct(&'a str),
+    Str,
+    Number,
+}
+
+/// Split a Lua script into tokens, each with its line, leaving out comments
+fn tokenize(source: &str) -> Vec<(Token<'_>, usize)> {
+    let bytes = source.as_bytes();
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let start = i;
+        let start_line = line;
+
+        match bytes[i] {
+            b'\n' => {
+                line += 1;
+                i += 1;
+            }
+            c if c.is_ascii_whitespace() => i += 1,
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                i += 2;
+
+                match long_bracket(bytes, i) {
+                    Some(level) => i = skip_long_bracket(bytes, i, level, &mut line),
+                    None => {
+                        while i < bytes.len() && bytes[i] != b'\n' {
+                            i += 1;
+                        }
+                    }
+                }
+            }
+            b'[' if long_bracket(bytes, i).is_some() => {
+                let level = long_bracket(bytes, i).unwrap_or_default();
+                i = skip_long_bracket(bytes, i, level, &mut line);
+                tokens.push((Token::Str, start_line));
+            }
+            quote @ (b'"' | b'\'') => {
+                i += 1;
+
+                while i < bytes.len() && bytes[i] != quote && bytes[i] != b'\n' {
+                    if bytes[i] == b'\\' {
+                        i += 1;
+
+                        if bytes.get(i) == Some(&b'\n') {
+                            line += 1;
+                        }
+                    }
+
+                    i += 1;
+                }
+
+                i += 1;
+                tokens.push((Token::Str, start_line));
+            }
+            c if c.is_ascii_alphabetic() || c == b'_' => {
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                    i += 1;
+                }
+
+                tokens.push((Token::Name(&source[start..i]), start_line));
+            }
+            c if c.is_ascii_digit() || (c == b'.' && bytes.get(i + 1).is_some_and(u8::is_ascii_digit)) => {
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'.'
+                    || ((bytes[i] == b'+' || bytes[i] == b'-') && matches!(bytes[i - 1], b'e' | b'E' | b'p' | b'P')))
+                {
+                    i += 1;
+                }
+
+                tokens.push((Token::Number, start_line));
+            }
+            _ => {
+                let length = ["...", "==", "~=", "<=", ">=", "..", "::", "//", "<<", ">>"].iter()
+                    .find(|punct| source[i..].starts_with(**punct))
+                    .map_or(1, |punct| punct.len());
+
+                // Stay on a character boundary when the script holds non-ASCII text outside strings
+                i += length;
+
+                while !source.is_char_boundary(i) {
+                    i += 1;
+                }
+
+                tokens.push((Token::Punct(&source[start..i]), start_line));
+            }
+        }
+    }
+
+    tokens
+}
+
+/// Level of the long bracket opening at i ([[ is 0, [==[ is 2)
+fn long_bracket(bytes: &[u8], i: usize) -> Option<usize> {
+    if bytes.get(i) != Some(&b'[') {
+        return None;
+    }
+
+    let level = bytes[i + 1..].iter().take_while(|&&c| c == b'=').count();
+
+    (bytes.get(i + 1 + level) == Some(&b'[')).then_some(level)
+}
+
+/// Skip a long string or comment opening at i, returning the index after its closing bracket
+fn skip_long_bracket(bytes: &[u8], i: usize, level: usize, line: &mut usize) -> usize {
+    let closing = format!("]{}]", "=".repeat(level));
+    let mut j = i + level + 2;
+
+    while j < bytes.len() {
+        if bytes[j..].starts_with(closing.as_bytes()) {
+            return j + closing.len();
+        }
+
+        if bytes[j] == b'\n' {
+            *line += 1;
+        }
+
+        j += 1;
+    }
+
+    j
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{declared_names, long_bracket, tokenize, Token};
+
+    /// Tokens of a script as written in the cases, "name", "(", "<str>" or "<number>", with lines
+    fn tokens(source: &str) -> Vec<(String, usize)> {
+        tokenize(source).into_iter()
+            .map(|(token, line)| match token {
+                Token::Name(name) | Token::Punct(name) => (name.to_string(), line),
+                Token::Str => ("<str>".to_string(), line),
+                Token::Number => ("<number>".to_string(), line),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tokenize_script() {
+        let cases: [(&str, &[(&str, usize)]); 12] = [
+            ("x = 1", &[("x", 1), ("=", 1), ("<number>", 1)]),
+            ("a.b:c(...)", &[("a", 1), (".", 1), ("b", 1), (":", 1), ("c", 1), ("(", 1), ("...", 1), (")", 1)]),
+            ("a ~= b .. c // 2", &[("a", 1), ("~=", 1), ("b", 1), ("..", 1), ("c", 1), ("//", 1), ("<number>", 1)]),
+            ("x = 0x1p-4 + 1e+10 + .5", &[("x", 1), ("=", 1), ("<number>", 1), ("+", 1), ("<number>", 1), ("+", 1), ("<number>", 1)]),
+            // Escaped quotes do not end strings
+            (r#"s = "a \" b" .. 'c \' d' f()"#, &[("s", 1), ("=", 1), ("<str>", 1), ("..", 1), ("<str>", 1), ("f", 1), ("(", 1), (")", 1)]),
+            (r#"s = "a \\" f()"#, &[("s", 1), ("=", 1), ("<str>", 1), ("f", 1), ("(", 1), (")", 1)]),
+            // Comments are left out, long ones only end at the bracket of their level
+            ("-- f()\ng()", &[("g", 2), ("(", 2), (")", 2)]),
+            ("--[[ f()\n]] g()", &[("g", 2), ("(", 2), (")", 2)]),
+            ("--[==[ ]] ]=] f()\n]==] g()", &[("g", 2), ("(", 2), (")", 2)]),
+            ("--[= f()\ng()", &[("g", 2), ("(", 2), (")", 2)]),
+            // Lines keep counting across multiline strings, tokens have the line they start on
+            ("s = [[\na\n]] f()\ns = [=[\n]]\n]=] g()\ns = 'a\\\nb' h()", &[
+                ("s", 1), ("=", 1), ("<str>", 1), ("f", 3), ("(", 3), (")", 3),
+                ("s", 4), ("=", 4), ("<str>", 4), ("g", 6), ("(", 6), (")", 6),
+                ("s", 7), ("=", 7), ("<str>", 7), ("h", 8), ("(", 8), (")", 8),
+            ]),
+            ("s = 'é' ¤ t", &[("s", 1), ("=", 1), ("<str>", 1), ("¤", 1), ("t", 1)]),
+        ];
+
+        for (source, expected) in cases {
+            let expected: Vec<(String, usize)> = expected.iter().map(|(token, line)| (token.to_string(), *line)).collect();
+            assert_eq!(tokens(source), expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn long_brackets() {
+        let cases = [("[[", Some(0)), ("[==[", Some(2)), ("[=", None), ("[=]", None), ("[ [", None), ("x", None), ("[", None)];
+
+        for (source, level) in cases {
+            assert_eq!(long_bracket(source.as_bytes(), 0), level, "{}", source);
+        }
+    }
+
+    #[test]
+    fn declared() {
+        let cases: [(&str, &[&str]); 8] = [
+            ("local a, b = 1, 2", &["a", "b"]),
+            ("local x <const>, y <close> = 1, f()", &["x", "y"]),
+            ("for k, v in pairs(t) do end", &["k", "v"]),
+            ("for i = 1, 10 do end", &["i"]),
+            ("function f(a, b, ...) end", &["f", "a", "b"]),
+            ("local function g(x) end", &["g", "x"]),
+            ("t.f = function(x) end; M.a = 1; C = 2", &["x", "C"]),
+            ("function t.f(x) end function t:m(y) end", &["x", "y"]),
+        ];
+
+        for (source, expected) in cases {
+            let tokens = tokenize(source);
+            let mut names: Vec<&str> = declared_names(&tokens).into_iter().collect();
+            let mut expected = expected.to_vec();
+
+            names.sort();
+            expected.sort();
+            assert_eq!(names, expected, "{}", source);
+        }
+    }
+}
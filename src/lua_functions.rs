@@ -1,13 +1,28 @@
 use mlua::{Error as LuaError, Lua, Result as LuaResult, Table, Value};
-use std::{fs, path::{Path, PathBuf}, sync::Arc};
+use std::{fs, path::{Path, PathBuf}};
 use serde_json::Value as JsonValue;
 use regex::Regex;
 
-use crate::file_operations::{copy_dir_all, download_file_blocking, sha256sum_file, extract_tarball};
-use crate::path_utils::{sanitize_path, validate_absolute_path};
+use crate::error::Error;
+use crate::file_operations::{copy_dir_all, download_file_blocking, execute_in_root, sha256sum_file, extract_tarball};
+use crate::path_utils::{sanitize_path, validate_absolute_path, PathError};
+use crate::version::Version;
+
+/// Convert an error raised inside a Lua callback into a Lua error pointing at the calling script line
+pub fn script_error(lua: &Lua, error: impl Into<Error>) -> LuaError {
+    let error = error.into();
+
+    let location = lua.inspect_stack(1).and_then(|debug| {
+        let line = debug.curr_line();
+        let source = debug.source().short_src?.into_owned();
+        (line > 0).then(|| format!("{}:{}: ", source, line))
+    });
+
+    LuaError::RuntimeError(format!("{}{}", location.unwrap_or_default(), error))
+}
 
 /// Convert JSON value to Lua value
-pub fn json_to_lua_table<'lua>(lua: &'lua Lua, value: &JsonValue) -> LuaResult<Value> {
+pub fn json_to_lua_table(lua: &Lua, value: &JsonValue) -> LuaResult<Value> {
     match value {
         JsonValue::Object(map) => {
             let table = lua.create_table()?;
@@ -39,9 +54,12 @@ pub fn json_to_lua_table<'lua>(lua: &'lua Lua, value: &JsonValue) -> LuaResult<V
 }
 
 
+/// Capture groups returned by regex_match (major, minor, patch, revision)
+type RegexCaptures = (Option<String>, Option<String>, Option<String>, Option<String>);
+
 /// Regex match function for Lua
-pub fn regex_match<'lua>(_: &'lua Lua, (text, pattern): (String, String)) -> LuaResult<(Option<String>, Option<String>, Option<String>, Option<String>)> {
-    let re = Regex::new(&pattern).map_err(|e| LuaError::RuntimeError(e.to_string()))?;
+pub fn regex_match(lua: &Lua, (text, pattern): (String, String)) -> LuaResult<RegexCaptures> {
+    let re = Regex::new(&pattern).map_err(|e| script_error(lua, e))?;
 
     if let Some(caps) = re.captures(&text) {
         let major = caps.get(1).map(|m| m.as_str().to_string());
@@ -55,40 +73,74 @@ pub fn regex_match<'lua>(_: &'lua Lua, (text, pattern): (String, String)) -> Lua
     }
 }
 
-pub fn register_git_object(lua: &Lua, src_dir: PathBuf, dst_dir: PathBuf) -> LuaResult<()> {
+/// Working directories of the git repositories cloned or loaded by the build script
+#[derive(Default)]
+pub struct SourceRepositories(pub Vec<PathBuf>);
+
+/// Package directory the copy and link functions write to, switched for every split package
+pub struct PackageDir(pub PathBuf);
+
+/// Point PKG_DIR, copy and link at a package directory
+pub fn set_package_dir(lua: &Lua, pkg_dir: PathBuf) -> LuaResult<()> {
+    lua.globals().set("PKG_DIR", pkg_dir.clone())?;
+    lua.set_app_data(PackageDir(pkg_dir));
+
+    Ok(())
+}
+
+/// Package directory currently written by the build script
+fn package_dir(lua: &Lua) -> LuaResult<PathBuf> {
+    lua.app_data_ref::<PackageDir>()
+        .map(|pkg_dir| pkg_dir.0.clone())
+        .ok_or_else(|| LuaError::RuntimeError("package directory is not set".to_string()))
+}
+
+/// Remember a repository used as a package source
+pub fn record_source_repository(lua: &Lua, repo: &git2::Repository) {
+    if let (Some(workdir), Some(mut repositories)) = (repo.workdir(), lua.app_data_mut::<SourceRepositories>()) {
+        repositories.0.push(workdir.to_path_buf());
+    }
+}
+
+pub fn register_git_object(lua: &Lua, src_dir: PathBuf) -> LuaResult<()> {
     let globals = lua.globals();
 
+    lua.set_app_data(SourceRepositories::default());
+
     // Register the git object
     let git_table = lua.create_table()?;
 
-    let git_repo_get_tags_function = lua.create_function(|_, repo: Table| {
-        let repo_path = repo.get::<String>("path").unwrap();
-        let repo = git2::Repository::open(&repo_path).unwrap();
+    let git_repo_get_tags_function = lua.create_function(|lua, repo: Table| {
+        let repo_path = repo.get::<String>("path")?;
+        let repo = git2::Repository::open(&repo_path).map_err(|e| script_error(lua, e))?;
 
-        let tags = repo.tag_names(None).unwrap();
-        let tags: Vec<String> = tags.iter().map(|t| t.unwrap().to_string()).collect();
+        let tags = repo.tag_names(None).map_err(|e| script_error(lua, e))?;
+        let tags: Vec<String> = tags.iter().flatten().map(|t| t.to_string()).collect();
 
         Ok(tags)
     })?;
 
-    let git_repo_get_revision_function = lua.create_function(|_, (repo, from): (Table, String)| {
-        let repo_path = repo.get::<String>("path").unwrap();
-        let repo = git2::Repository::open(&repo_path).unwrap();
+    let git_repo_get_revision_function = lua.create_function(|lua, (repo, from): (Table, String)| {
+        let repo_path = repo.get::<String>("path")?;
 
         // get number of commits since tag or commit called from
-        let from = repo.revparse_single(&from).unwrap();
-        let from = from.peel_to_commit().unwrap();
-        let from_id = from.id();
+        let count = (|| -> Result<usize, git2::Error> {
+            let repo = git2::Repository::open(&repo_path)?;
+
+            let from = repo.revparse_single(&from)?;
+            let from = from.peel_to_commit()?;
+            let from_id = from.id();
 
-        let head = repo.head().unwrap();
-        let head = head.peel_to_commit().unwrap();
-        let head_commit_id = head.id();
+            let head = repo.head()?;
+            let head = head.peel_to_commit()?;
+            let head_commit_id = head.id();
 
-        let mut revwalk = repo.revwalk().unwrap();
-        revwalk.push(head_commit_id).unwrap();
-        revwalk.hide(from_id).unwrap();
+            let mut revwalk = repo.revwalk()?;
+            revwalk.push(head_commit_id)?;
+            revwalk.hide(from_id)?;
 
-        let count = revwalk.count();
+            Ok(revwalk.count())
+        })().map_err(|e| script_error(lua, e))?;
 
         Ok(count)
     })?;
@@ -98,24 +150,23 @@ pub fn register_git_object(lua: &Lua, src_dir: PathBuf, dst_dir: PathBuf) -> Lua
     let git_close_git_repo_get_tags_function = git_repo_get_tags_function.clone();
     let git_close_git_repo_get_revision_function = git_repo_get_revision_function.clone();
     let git_clone_function = lua.create_function(move |ilua, (src, dest): (String, Option<String>)| {
-        println!("Cloning git repository from {} to {}", src, sanitize_path(&git_clone_src_dir.clone(), &dest.clone().unwrap_or_else(|| ".".to_string())).unwrap().to_str().unwrap());
+        let dest = sanitize_path(&git_clone_src_dir, &dest.unwrap_or_else(|| ".".to_string()))
+            .map_err(|e| script_error(ilua, e))?;
+
+        println!("Cloning git repository from {} to {}", src, dest.display());
 
         // ensure the destination exists
-        if let Some(parent) = sanitize_path(&git_clone_src_dir, &dest.clone().unwrap_or_else(|| ".".to_string())).unwrap().parent() {
-            if !parent.exists() {
-                println!("Creating parent directories for {:?}", parent);
-                fs::create_dir_all(parent).map_err(|e| LuaError::RuntimeError(e.to_string()))?;
-            }
+        if let Some(parent) = dest.parent()
+            && !parent.exists()
+        {
+            println!("Creating parent directories for {:?}", parent);
+            fs::create_dir_all(parent).map_err(|e| script_error(ilua, e))?;
         }
 
-        let repo = git2::Repository::clone(&src, sanitize_path(&git_clone_src_dir, &dest.unwrap_or_else(|| ".".to_string())).unwrap()).map_err(|e| LuaError::RuntimeError(e.to_string()))?;
-
-        let table = ilua.create_table()?;
-        table.set("path", repo.path().parent().unwrap().to_str().unwrap_or(""))?;
-        table.set("get_tags", git_close_git_repo_get_tags_function.clone())?;
-        table.set("get_revision", git_close_git_repo_get_revision_function.clone())?;
+        let repo = git2::Repository::clone(&src, &dest).map_err(|e| script_error(ilua, e))?;
+        record_source_repository(ilua, &repo);
 
-        Ok(table)
+        git_repo_table(ilua, &repo, &git_close_git_repo_get_tags_function, &git_close_git_repo_get_revision_function)
     })?;
     git_table.set("clone", git_clone_function)?;
 
@@ -124,14 +175,11 @@ pub fn register_git_object(lua: &Lua, src_dir: PathBuf, dst_dir: PathBuf) -> Lua
     let git_load_git_repo_get_tags_function = git_repo_get_tags_function.clone();
     let git_load_git_repo_get_revision_function = git_repo_get_revision_function.clone();
     let git_load_function = lua.create_function(move |ilua, repo: String| {
-        let repo = git2::Repository::open(sanitize_path(&git_load_src_dir, &repo).unwrap()).map_err(|e| LuaError::RuntimeError(e.to_string()))?;
-
-        let table = ilua.create_table()?;
-        table.set("path", repo.path().parent().unwrap().to_str().unwrap_or("")).map_err(|e| LuaError::RuntimeError(e.to_string()))?;
-        table.set("get_tags", git_load_git_repo_get_tags_function.clone()).map_err(|e| LuaError::RuntimeError(e.to_string()))?;
-        table.set("get_revision", git_load_git_repo_get_revision_function.clone()).map_err(|e| LuaError::RuntimeError(e.to_string()))?;
+        let repo_path = sanitize_path(&git_load_src_dir, &repo).map_err(|e| script_error(ilua, e))?;
+        let repo = git2::Repository::open(repo_path).map_err(|e| script_error(ilua, e))?;
+        record_source_repository(ilua, &repo);
 
-        Ok(table)
+        git_repo_table(ilua, &repo, &git_load_git_repo_get_tags_function, &git_load_git_repo_get_revision_function)
     })?;
     git_table.set("load", git_load_function)?;
 
@@ -140,6 +188,53 @@ pub fn register_git_object(lua: &Lua, src_dir: PathBuf, dst_dir: PathBuf) -> Lua
     Ok(())
 }
 
+/// Build the git_repo table handed to Lua for an opened repository
+fn git_repo_table(lua: &Lua, repo: &git2::Repository, get_tags: &mlua::Function, get_revision: &mlua::Function) -> LuaResult<Table> {
+    let workdir = repo.workdir()
+        .ok_or_else(|| script_error(lua, git2::Error::from_str("bare repositories are not supported")))?;
+
+    let table = lua.create_table()?;
+    table.set("path", workdir.to_str().unwrap_or(""))?;
+    table.set("get_tags", get_tags.clone())?;
+    table.set("get_revision", get_revision.clone())?;
+
+    Ok(table)
+}
+
+pub fn register_version_object(lua: &Lua) -> LuaResult<()> {
+    let globals = lua.globals();
+
+    // Register the version object
+    let version_table = lua.create_table()?;
+
+    let version_parse_function = lua.create_function(|lua, version: String| {
+        let version: Version = version.parse().map_err(|e| script_error(lua, e))?;
+
+        let table = lua.create_table()?;
+        table.set("epoch", version.epoch)?;
+        table.set("major", version.major)?;
+        table.set("minor", version.minor)?;
+        table.set("patch", version.patch)?;
+        table.set("pre", version.pre)?;
+        table.set("revision", version.revision)?;
+
+        Ok(table)
+    })?;
+    version_table.set("parse", version_parse_function)?;
+
+    let version_compare_function = lua.create_function(|lua, (a, b): (String, String)| {
+        let a: Version = a.parse().map_err(|e| script_error(lua, e))?;
+        let b: Version = b.parse().map_err(|e| script_error(lua, e))?;
+
+        Ok(a.cmp(&b) as i8)
+    })?;
+    version_table.set("compare", version_compare_function)?;
+
+    globals.set("version", version_table)?;
+
+    Ok(())
+}
+
 /// Register all Lua functions
 pub fn register_lua_functions(lua: &Lua, src_dir: PathBuf, pkg_dir: PathBuf) -> LuaResult<()> {
     let globals = lua.globals();
@@ -147,55 +242,45 @@ pub fn register_lua_functions(lua: &Lua, src_dir: PathBuf, pkg_dir: PathBuf) ->
     // Set global constants
     globals.set("ARCH", std::env::consts::ARCH)?;
     globals.set("SRC_DIR", src_dir.clone())?;
-    globals.set("PKG_DIR", pkg_dir.clone())?;
+    set_package_dir(lua, pkg_dir)?;
 
     // Register download function (only downloads to src_dir)
     let download_src_dir = src_dir.clone();
-    let download_function = lua.create_function(move |_, (url, dest): (String, String)| {
-        match download_file_blocking(&url, &download_src_dir, &dest) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(LuaError::RuntimeError(format!("Download error: {}", e))),
-        }
+    let download_function = lua.create_function(move |lua, (url, dest): (String, String)| {
+        download_file_blocking(&url, &download_src_dir, &dest)
+            .map(|_| ())
+            .map_err(|e| script_error(lua, e))
     })?;
     globals.set("download", download_function)?;
 
     // Register JSON decode function
     let json_decode_function = lua.create_function(|lua, json_str: String| {
         let json_value: JsonValue = serde_json::from_str(&json_str)
-            .map_err(|e| LuaError::ExternalError(Arc::new(e)))?;
+            .map_err(|e| script_error(lua, e))?;
         json_to_lua_table(lua, &json_value)
     })?;
     globals.set("json_decode", json_decode_function)?;
 
     // Register file_load function (only reads from src_dir)
     let file_load_src_dir = src_dir.clone();
-    let file_load_function = lua.create_function(move |_, path: String| {
-        match sanitize_path(&file_load_src_dir, &path) {
-            Ok(abs_path) => {
-                fs::read_to_string(&abs_path)
-                    .map_err(|e| LuaError::ExternalError(Arc::new(e)))
-            },
-            Err(e) => Err(LuaError::RuntimeError(format!("Path error: {}", e))),
-        }
+    let file_load_function = lua.create_function(move |lua, path: String| {
+        let abs_path = sanitize_path(&file_load_src_dir, &path).map_err(|e| script_error(lua, e))?;
+
+        fs::read_to_string(&abs_path).map_err(|e| script_error(lua, e))
     })?;
     globals.set("file_load", file_load_function)?;
 
     // Register file_save function (only writes to src_dir)
     let file_save_src_dir = src_dir.clone();
-    let file_save_function = lua.create_function(move |_, (path, content): (String, String)| {
-        match sanitize_path(&file_save_src_dir, &path) {
-            Ok(abs_path) => {
-                // Ensure parent directory exists
-                if let Some(parent) = abs_path.parent() {
-                    fs::create_dir_all(parent).map_err(|e| LuaError::ExternalError(Arc::new(e)))?;
-                }
-                
-                fs::write(&abs_path, content)
-                    .map_err(|e| LuaError::ExternalError(Arc::new(e)))?;
-                Ok(())
-            },
-            Err(e) => Err(LuaError::RuntimeError(format!("Path error: {}", e))),
+    let file_save_function = lua.create_function(move |lua, (path, content): (String, String)| {
+        let abs_path = sanitize_path(&file_save_src_dir, &path).map_err(|e| script_error(lua, e))?;
+
+        // Ensure parent directory exists
+        if let Some(parent) = abs_path.parent() {
+            fs::create_dir_all(parent).map_err(|e| script_error(lua, e))?;
         }
+
+        fs::write(&abs_path, content).map_err(|e| script_error(lua, e))
     })?;
     globals.set("file_save", file_save_function)?;
 
@@ -205,115 +290,87 @@ pub fn register_lua_functions(lua: &Lua, src_dir: PathBuf, pkg_dir: PathBuf) ->
 
     // Register sha256sum_file function (only works on src_dir)
     let sha256_src_dir = src_dir.clone();
-    let sha256sum_file_function = lua.create_function(move |_, path: String| {
-        match sanitize_path(&sha256_src_dir, &path) {
-            Ok(abs_path) => {
-                match sha256sum_file(&abs_path) {
-                    Ok(hash) => Ok(hash),
-                    Err(e) => Err(LuaError::ExternalError(Arc::new(e))),
-                }
-            },
-            Err(e) => Err(LuaError::RuntimeError(format!("Path error: {}", e))),
-        }
+    let sha256sum_file_function = lua.create_function(move |lua, path: String| {
+        let abs_path = sanitize_path(&sha256_src_dir, &path).map_err(|e| script_error(lua, e))?;
+
+        sha256sum_file(&abs_path).map_err(|e| script_error(lua, e))
     })?;
     globals.set("sha256sum_file", sha256sum_file_function)?;
 
     // Register unpack_tarball function (works within src_dir)
     let unpack_src_dir = src_dir.clone();
-    let unpack_tarball_function = lua.create_function(move |_, (path, dest): (String, String)| {
-        match sanitize_path(&unpack_src_dir, &path) {
-            Ok(abs_path) => {
-                match validate_absolute_path(Path::new(&dest)) {
-                    Ok(abs_dest) => {
-                        match extract_tarball(&abs_path, &abs_dest) {
-                            Ok(_) => Ok(()),
-                            Err(e) => Err(LuaError::ExternalError(Arc::new(e))),
-                        }
-                    },
-                    Err(e) => Err(LuaError::RuntimeError(format!("Path error: {}", e))),
-                }
-            },
-            Err(e) => Err(LuaError::RuntimeError(format!("Path error: {}", e))),
-        }
+    let unpack_tarball_function = lua.create_function(move |lua, (path, dest): (String, String)| {
+        let abs_path = sanitize_path(&unpack_src_dir, &path).map_err(|e| script_error(lua, e))?;
+        let abs_dest = validate_absolute_path(Path::new(&dest)).map_err(|e| script_error(lua, e))?;
+
+        extract_tarball(&abs_path, &abs_dest).map_err(|e| script_error(lua, e))
     })?;
     globals.set("unpack_tarball", unpack_tarball_function)?;
 
     // Register copy function (works for both files and directories, within src_dir to pkg_dir)
     let copy_src_dir = src_dir.clone();
-    let copy_pkg_dir = pkg_dir.clone(); // Add this line to clone pkg_dir
-    let copy_function = lua.create_function(move |_, (src, dest): (String, String)| {
-        match sanitize_path(&copy_src_dir, &src) {
-            Ok(abs_src) => {
-                // Use pkg_dir as base for destination path
-                match sanitize_path(&copy_pkg_dir, &dest) {
-                    Ok(abs_dest) => {
-                        // Check if source is a file or directory and use appropriate copy function
-                        if abs_src.is_file() {
-                            // Create parent directories if they don't exist
-                            if let Some(parent) = abs_dest.parent() {
-                                if !parent.exists() {
-                                    if let Err(e) = std::fs::create_dir_all(parent) {
-                                        return Err(LuaError::ExternalError(Arc::new(e)));
-                                    }
-                                }
-                            }
-                            
-                            println!("Copying file {:?} to {:?}", abs_src, &abs_dest);
-
-                            match std::fs::copy(&abs_src, &abs_dest) {
-                                Ok(_) => Ok(()),
-                                Err(e) => Err(LuaError::ExternalError(Arc::new(e))),
-                            }
-                        } else if abs_src.is_dir() {
-                            println!("Copying directory {:?} to {:?}", abs_src, &abs_dest);
-
-                            match copy_dir_all(&abs_src, &abs_dest) {
-                                Ok(_) => Ok(()),
-                                Err(e) => Err(LuaError::ExternalError(Arc::new(e))),
-                            }
-                        } else {
-                            Err(LuaError::RuntimeError(format!("Source path is neither a file nor directory: {:?}", abs_src)))
-                        }
-                    },
-                    Err(e) => Err(LuaError::RuntimeError(format!("Path error: {}", e))),
-                }
-            },
-            Err(e) => Err(LuaError::RuntimeError(format!("Path error: {}", e))),
+    let copy_function = lua.create_function(move |lua, (src, dest): (String, String)| {
+        let abs_src = sanitize_path(&copy_src_dir, &src).map_err(|e| script_error(lua, e))?;
+        // Use pkg_dir as base for destination path
+        let abs_dest = sanitize_path(&package_dir(lua)?, &dest).map_err(|e| script_error(lua, e))?;
+
+        // Check if source is a file or directory and use appropriate copy function
+        if abs_src.is_file() {
+            // Create parent directories if they don't exist
+            if let Some(parent) = abs_dest.parent()
+                && !parent.exists()
+            {
+                fs::create_dir_all(parent).map_err(|e| script_error(lua, e))?;
+            }
+
+            println!("Copying file {:?} to {:?}", abs_src, &abs_dest);
+
+            fs::copy(&abs_src, &abs_dest).map(|_| ()).map_err(|e| script_error(lua, e))
+        } else if abs_src.is_dir() {
+            println!("Copying directory {:?} to {:?}", abs_src, &abs_dest);
+
+            copy_dir_all(&abs_src, &abs_dest).map_err(|e| script_error(lua, e))
+        } else {
+            Err(script_error(lua, Error::Path(PathError::InvalidPath(format!("Source path is neither a file nor directory: {:?}", abs_src)))))
         }
     })?;
     globals.set("copy", copy_function)?;
 
-    // Register the link function (src is absolute path destination, dest is within pkg_dir as the symlink)
-    let link_pkg_dir = pkg_dir.clone();
-    let link_function = lua.create_function(move |_, (target, link_path): (String, String)| {
-        // Validate that the target path exists
-        match validate_absolute_path(Path::new(&target)) {
-            Ok(abs_target) => {
-                // Sanitize the link_path to be within pkg_dir
-                match sanitize_path(&link_pkg_dir, &link_path) {
-                    Ok(abs_link) => {
-                        // Create parent directories for the symlink if they don't exist
-                        if let Some(parent) = abs_link.parent() {
-                            if let Err(e) = std::fs::create_dir_all(parent) {
-                                return Err(LuaError::ExternalError(Arc::new(e)));
-                            }
-                        }
-                        
-                        println!("Creating symlink at {:?} pointing to {:?}", abs_link, abs_target);
-                        
-                        // Create the symlink: first param is target (where it points to), second is link (where symlink is created)
-                        match std::os::unix::fs::symlink(&abs_target, &abs_link) {
-                            Ok(_) => Ok(()),
-                            Err(e) => Err(LuaError::ExternalError(Arc::new(e))),
-                        }
-                    },
-                    Err(e) => Err(LuaError::RuntimeError(format!("Path error: {}", e))),
-                }
-            },
-            Err(e) => Err(LuaError::RuntimeError(format!("Path error: {}", e))),
+    // Register the link function (target is where the symlink points to once installed, link_path is within pkg_dir)
+    let link_function = lua.create_function(move |lua, (target, link_path): (String, String)| {
+        // The target usually only exists once the package is installed, dangling links are reported by the package checks
+        let abs_target = PathBuf::from(&target);
+        // Sanitize the link_path to be within pkg_dir
+        let abs_link = sanitize_path(&package_dir(lua)?, &link_path).map_err(|e| script_error(lua, e))?;
+
+        // Create parent directories for the symlink if they don't exist
+        if let Some(parent) = abs_link.parent() {
+            fs::create_dir_all(parent).map_err(|e| script_error(lua, e))?;
         }
+
+        println!("Creating symlink at {:?} pointing to {:?}", abs_link, abs_target);
+
+        // Create the symlink: first param is target (where it points to), second is link (where symlink is created)
+        std::os::unix::fs::symlink(&abs_target, &abs_link).map_err(|e| script_error(lua, e))
     })?;
     globals.set("link", link_function)?;
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Register the globals available to install scriptlets
+pub fn register_scriptlet_functions(lua: &Lua, root: PathBuf) -> LuaResult<()> {
+    let globals = lua.globals();
+
+    // Set global constants
+    globals.set("ARCH", std::env::consts::ARCH)?;
+    globals.set("ROOT", root.clone())?;
+
+    // Register execute function (runs a shell command, chrooted into root unless it is the host root)
+    let execute_function = lua.create_function(move |lua, command: String| {
+        execute_in_root(&root, &command).map_err(|e| script_error(lua, e))
+    })?;
+    globals.set("execute", execute_function)?;
+
+    Ok(())
+}
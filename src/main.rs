@@ -1,169 +1,60 @@
-use lua_functions::{register_git_object, register_lua_functions};
-use mlua::{FromLuaMulti, Function, IntoLuaMulti, Lua, Table, Value};
-use std::{fs, path::{Path, PathBuf}, process};
-use clap::{command, value_parser, Arg};
-use serde::{Deserialize, Serialize};
+use archive::{read_metadata_files, Compression};
+use build::{build_package, verify_reproducible, BuildOptions};
+use config::Config;
+use database::Database;
+use dependency::Dependency;
+use error::{Error, Result};
+use install::install_packages;
+use lint::lint_project;
+use remove::remove_packages;
+use sync::{load_sync_packages, sync_repositories};
+use upgrade::upgrade_packages;
+use manifest::Manifest;
+use version::Version;
+use std::{fs, io, path::{Path, PathBuf}, process, str::FromStr};
+use clap::{command, value_parser, Arg, ArgAction, ArgMatches, Command};
 mod lua_functions;
+mod archive;
+mod build;
+mod config;
+mod database;
+mod dependency;
+mod elf;
+mod error;
 mod file_operations;
+mod history;
+mod install;
+mod lint;
+mod manifest;
+mod package_info;
 mod path_utils;
+mod qa;
+mod query;
+mod remove;
+mod repository;
+mod resolver;
+mod scriptlet;
+mod shlib;
+mod source;
+mod spdx;
+mod strip;
+mod sync;
+mod transaction;
+mod trigger;
+mod upgrade;
+mod version;
 
-#[derive(Serialize, Deserialize)]
-struct PackageInfo {
-    name: String,
-    description: String,
-    version: Option<String>,
-    license: String,
-    dev: bool,
-    dependencies: Vec<String>,
-    build_dependencies: Vec<String>,
-    optional_dependencies: Vec<String>,
-    conflicts: Vec<String>,
-    provides: Vec<String>,
-    replaces: Vec<String>,
-    arch: Vec<String>,
-    url: String,
-    maintainers: Vec<String>,
-}
-
-#[derive(Serialize, Deserialize)]
-struct FinalPackageInfo {
-    name: String,
-    description: String,
-    version: String,
-    license: String,
-    dev: bool,
-    dependencies: Vec<String>,
-    conflicts: Vec<String>,
-    provides: Vec<String>,
-    replaces: Vec<String>,
-    arch: Vec<String>,
-    url: String,
-    maintainers: Vec<String>,
-}
-
-impl Into<FinalPackageInfo> for PackageInfo {
-    fn into(self) -> FinalPackageInfo {
-        FinalPackageInfo {
-            name: self.name,
-            description: self.description,
-            version: self.version.unwrap_or_else(|| "0.0.0".to_string()),
-            license: self.license,
-            dev: self.dev,
-            dependencies: self.dependencies,
-            conflicts: self.conflicts,
-            provides: self.provides,
-            replaces: self.replaces,
-            arch: self.arch,
-            url: self.url,
-            maintainers: self.maintainers,
-        }
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        process::exit(e.exit_code());
     }
 }
 
-fn lua_get_package_info(lua: &Lua) -> Result<PackageInfo, mlua::Error> {
-    let globals = lua.globals();
-
-    // Get the INFO table
-    let info_table: Table = globals.get("INFO").map_err(|_| {
-        mlua::Error::RuntimeError("INFO table missing".to_string())
-    })?;
-
-    // Required fields
-    let name = info_table.get("name").map_err(|_| {
-        mlua::Error::RuntimeError("name field missing".to_string())
-    })?;
-    
-    let description = info_table.get("description").map_err(|_| {
-        mlua::Error::RuntimeError("description field missing".to_string())
-    })?;
-    
-    let url = info_table.get("url").map_err(|_| {
-        mlua::Error::RuntimeError("url field missing".to_string())
-    })?;
-    
-    let license = info_table.get("license").map_err(|_| {
-        mlua::Error::RuntimeError("license field missing".to_string())
-    })?;
-    
-    let dev = info_table.get("dev").map_err(|_| {
-        mlua::Error::RuntimeError("dev field missing".to_string())
-    })?;
-
-    // Required array fields
-    let provides_table: Table = info_table.get("provides").map_err(|_| {
-        mlua::Error::RuntimeError("provides field missing".to_string())
-    })?;
-    
-    let arch_table: Table = info_table.get("arch").map_err(|_| {
-        mlua::Error::RuntimeError("arch field missing".to_string())
-    })?;
-
-    // Optional version field (can be None)
-    let version: Option<String> = info_table.get("version").ok();
-    
-    // Get maintainers
-    let maintainers_table: Table = info_table.get("maintainers").map_err(|_| {
-        mlua::Error::RuntimeError("maintainers field missing".to_string())
-    })?;
-
-    // Optional array fields - initialize as empty arrays if missing
-    let dependencies = match info_table.get::<Table>("dependencies") {
-        Ok(table) => table.sequence_values::<String>().map(|v| v.unwrap_or_default()).collect(),
-        Err(_) => Vec::new(),
-    };
-
-    let build_dependencies = match info_table.get::<Table>("build_dependencies") {
-        Ok(table) => table.sequence_values::<String>().map(|v| v.unwrap_or_default()).collect(),
-        Err(_) => Vec::new(),
-    };
-
-    let optional_dependencies = match info_table.get::<Table>("optional_dependencies") {
-        Ok(table) => table.sequence_values::<String>().map(|v| v.unwrap_or_default()).collect(),
-        Err(_) => Vec::new(),
-    };
-
-    let conflicts = match info_table.get::<Table>("conflicts") {
-        Ok(table) => table.sequence_values::<String>().map(|v| v.unwrap_or_default()).collect(),
-        Err(_) => Vec::new(),
-    };
-
-    let provides = provides_table.sequence_values::<String>()
-        .map(|v| v.unwrap_or_default())
-        .collect();
-
-    let replaces = match info_table.get::<Table>("replaces") {
-        Ok(table) => table.sequence_values::<String>().map(|v| v.unwrap_or_default()).collect(),
-        Err(_) => Vec::new(),
-    };
-
-    let arch = arch_table.sequence_values::<String>()
-        .map(|v| v.unwrap_or_default())
-        .collect();
-
-    let maintainers = maintainers_table.sequence_values::<String>()
-        .map(|v| v.unwrap_or_default())
-        .collect();
-
-    Ok(PackageInfo {
-        name,
-        description,
-        version,
-        license,
-        dev,
-        dependencies,
-        build_dependencies,
-        optional_dependencies,
-        conflicts,
-        provides,
-        replaces,
-        arch,
-        url,
-        maintainers,
-    })
-}
-
-fn main() {
+fn run() -> Result<()> {
     let matches = command!()
+        .args_conflicts_with_subcommands(true)
+        .subcommand_negates_reqs(true)
         .arg(Arg::new("project")
             .required(true)
             .help("The project to build")
@@ -173,223 +64,370 @@ fn main() {
             .short('C')
             .long("clean")
             .required(false)
-            .num_args(0)
+            .action(ArgAction::SetTrue)
             .help("Clean the project after building"))
         .arg(Arg::new("clean_before")
             .short('c')
             .long("clean-before")
             .required(false)
-            .num_args(0)
+            .action(ArgAction::SetTrue)
             .help("Clean the project before building"))
+        .arg(Arg::new("nocheck")
+            .long("nocheck")
+            .required(false)
+            .action(ArgAction::SetTrue)
+            .help("Do not run the CHECK phase"))
+        .arg(Arg::new("compression")
+            .long("compression")
+            .required(false)
+            .value_name("CODEC[:LEVEL]")
+            .value_parser(Compression::from_str)
+            .help("Package compression: zstd, xz, gzip or none, with an optional level (e.g. zstd:19)"))
+        .arg(Arg::new("compression_threads")
+            .long("compression-threads")
+            .required(false)
+            .value_name("THREADS")
+            .value_parser(value_parser!(u32))
+            .help("Number of zstd compression threads, 0 uses every available core"))
+        .arg(Arg::new("verify_reproducible")
+            .long("verify-reproducible")
+            .required(false)
+            .action(ArgAction::SetTrue)
+            .help("Build the package twice from clean trees and check that both archives are identical"))
+        .arg(root_arg()
+            .help("Root whose installed packages provide the shared libraries and symlink targets the package needs"))
+        .subcommand(Command::new("verify")
+            .about("Check a package archive, or the files it installed, against its manifest")
+            .arg(Arg::new("package")
+                .required(true)
+                .help("The package archive to verify, or the name of an installed package")
+                .value_parser(value_parser!(PathBuf)))
+            .arg(Arg::new("root")
+                .long("root")
+                .required(false)
+                .value_name("DIR")
+                .value_parser(value_parser!(PathBuf))
+                .help("Check the files installed under this root instead of the archive contents")))
+        .subcommand(Command::new("lint")
+            .about("Check a build script without running it")
+            .arg(Arg::new("project")
+                .required(true)
+                .help("The project to check")
+                .value_parser(value_parser!(PathBuf))))
+        .subcommand(Command::new("install")
+            .about("Install package archives, or packages from the synced repositories by name")
+            .arg(Arg::new("packages")
+                .required(true)
+                .num_args(1..)
+                .help("Package archives, or package names with an optional constraint (e.g. openssl>=3.0.0)"))
+            .arg(root_arg()))
+        .subcommand(Command::new("sync")
+            .about("Download the indexes of the configured repositories")
+            .arg(root_arg()))
+        .subcommand(Command::new("upgrade")
+            .about("Upgrade installed packages to the newest versions of the synced repositories")
+            .arg(Arg::new("packages")
+                .required(false)
+                .num_args(1..)
+                .help("Names of the packages to upgrade, every installed package if none are given"))
+            .arg(Arg::new("dry_run")
+                .long("dry-run")
+                .required(false)
+                .action(ArgAction::SetTrue)
+                .help("Print the upgrade plan without changing anything"))
+            .arg(root_arg()))
+        .subcommand(Command::new("remove")
+            .about("Remove installed packages")
+            .arg(Arg::new("packages")
+                .required(true)
+                .num_args(1..)
+                .help("Names of the packages to remove"))
+            .arg(Arg::new("force")
+                .long("force")
+                .required(false)
+                .action(ArgAction::SetTrue)
+                .help("Remove the packages even if installed packages depend on them"))
+            .arg(root_arg()))
+        .subcommand(Command::new("query")
+            .about("Query the installed packages")
+            .subcommand_required(true)
+            .arg(root_arg().global(true))
+            .arg(Arg::new("json")
+                .long("json")
+                .required(false)
+                .global(true)
+                .action(ArgAction::SetTrue)
+                .help("Print the result as JSON"))
+            .subcommand(Command::new("list")
+                .about("List the installed packages with their versions"))
+            .subcommand(Command::new("info")
+                .about("Show the metadata of an installed package")
+                .arg(Arg::new("package")
+                    .required(true)
+                    .help("Name of the installed package")))
+            .subcommand(Command::new("files")
+                .about("List the files of an installed package")
+                .arg(Arg::new("package")
+                    .required(true)
+                    .help("Name of the installed package")))
+            .subcommand(Command::new("owns")
+                .about("Show which installed package owns a file")
+                .arg(Arg::new("path")
+                    .required(true)
+                    .help("Path of the file, relative to the root")
+                    .value_parser(value_parser!(PathBuf)))))
+        .subcommand(Command::new("repo")
+            .about("Maintain a repository of package archives and its index")
+            .subcommand_required(true)
+            .subcommand(Command::new("add")
+                .about("Add package archives to a repository, replacing older versions in the index")
+                .arg(repo_dir_arg())
+                .arg(Arg::new("packages")
+                    .required(true)
+                    .num_args(1..)
+                    .help("The package archives to add")
+                    .value_parser(value_parser!(PathBuf))))
+            .subcommand(Command::new("remove")
+                .about("Remove packages from the index of a repository")
+                .arg(repo_dir_arg())
+                .arg(Arg::new("packages")
+                    .required(true)
+                    .num_args(1..)
+                    .help("Names of the packages to remove")))
+            .subcommand(Command::new("rebuild")
+                .about("Regenerate the index from every package archive in a repository")
+                .arg(repo_dir_arg())))
+        .subcommand(Command::new("vercmp")
+            .about("Compare two versions, printing -1, 0 or 1")
+            .arg(Arg::new("a")
+                .required(true)
+                .help("The first version"))
+            .arg(Arg::new("b")
+                .required(true)
+                .help("The second version")))
         .get_matches();
 
-    let project = matches.get_one::<PathBuf>("project").unwrap();
-    let clean_project_before = matches.contains_id("clean_before");
-    let clean_project_after = matches.contains_id("clean_after");
+    match matches.subcommand() {
+        Some(("verify", verify_matches)) => verify_command(verify_matches),
+        Some(("vercmp", vercmp_matches)) => vercmp_command(vercmp_matches),
+        Some(("lint", lint_matches)) => lint_command(lint_matches),
+        Some(("install", install_matches)) => install_command(install_matches),
+        Some(("sync", sync_matches)) => sync_command(sync_matches),
+        Some(("upgrade", upgrade_matches)) => upgrade_command(upgrade_matches),
+        Some(("remove", remove_matches)) => remove_command(remove_matches),
+        Some(("query", query_matches)) => query_command(query_matches),
+        Some(("repo", repo_matches)) => repo_command(repo_matches),
+        _ => build_command(&matches),
+    }
+}
 
-    // check if the project is either a directory containing a buildpkg.lua file or a buildpkg.lua file
-    let buildpkg_lua = if project.is_dir() {
-        fs::canonicalize(project.join("buildpkg.lua")).unwrap()
-    } else {
-        fs::canonicalize(project.clone()).unwrap()
+/// The --root option of the commands working on installed packages
+fn root_arg() -> Arg {
+    Arg::new("root")
+        .long("root")
+        .required(false)
+        .value_name("DIR")
+        .default_value("/")
+        .value_parser(value_parser!(PathBuf))
+        .help("Root directory the packages are installed in")
+}
+
+/// The repository directory argument of the repo commands
+fn repo_dir_arg() -> Arg {
+    Arg::new("repository")
+        .required(true)
+        .help("The repository directory, holding the package archives and index.json")
+        .value_parser(value_parser!(PathBuf))
+}
+
+fn build_command(matches: &ArgMatches) -> Result<()> {
+    let project = matches.get_one::<PathBuf>("project").unwrap();
+    let clean_project_before = matches.get_flag("clean_before");
+    let clean_project_after = matches.get_flag("clean_after");
+    let skip_check = matches.get_flag("nocheck");
+
+    let config = Config::load()?;
+
+    let compression = matches.get_one::<Compression>("compression").copied()
+        .or(config.compression)
+        .unwrap_or_default();
+    let compression_threads = matches.get_one::<u32>("compression_threads").copied()
+        .or(config.compression_threads)
+        .unwrap_or(0);
+
+    let buildpkg_lua = buildpkg_lua_path(project)?;
+    let working_dir = buildpkg_lua.parent()
+        .map(Path::to_path_buf)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "buildpkg.lua has no parent directory"))?;
+
+    let options = BuildOptions {
+        buildpkg_lua,
+        working_dir,
+        clean_before: clean_project_before,
+        clean_after: clean_project_after,
+        skip_check,
+        compression,
+        compression_threads,
+        qa: config.qa,
+        root: matches.get_one::<PathBuf>("root").unwrap().clone(),
     };
 
-    if !buildpkg_lua.exists() {
-        eprintln!("Error: buildpkg.lua not found");
-        std::process::exit(1);
+    if matches.get_flag("verify_reproducible") {
+        verify_reproducible(&options)
+    } else {
+        build_package(&options).map(|_| ())
     }
+}
 
-    let working_dir = if project.is_dir() {
-        fs::canonicalize(project).unwrap()
+/// Path of the build script of a project, which is either a directory containing a buildpkg.lua
+/// file or a buildpkg.lua file
+fn buildpkg_lua_path(project: &Path) -> Result<PathBuf> {
+    let buildpkg_lua = if project.is_dir() {
+        project.join("buildpkg.lua")
     } else {
-        fs::canonicalize(project.parent().unwrap().to_path_buf()).unwrap()
+        project.to_path_buf()
     };
 
-    if clean_project_before {
-        if working_dir.join("src").exists() {
-            fs::remove_dir_all(working_dir.join("src")).unwrap();
-        }
-
-        if working_dir.join("pkg").exists() {
-            fs::remove_dir_all(working_dir.join("pkg")).unwrap();
-        }
+    if !buildpkg_lua.is_file() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("buildpkg.lua not found at {:?}", buildpkg_lua)).into());
     }
 
-    let lua = Lua::new();
-
-    if !working_dir.join("src").exists() {
-        fs::create_dir(working_dir.join("src")).unwrap();
-    }
+    Ok(fs::canonicalize(buildpkg_lua)?)
+}
 
-    let src_dir_value = working_dir.join("src");
+fn lint_command(matches: &ArgMatches) -> Result<()> {
+    let project = matches.get_one::<PathBuf>("project").unwrap();
 
-    if !working_dir.join("pkg").exists() {
-        fs::create_dir(working_dir.join("pkg")).unwrap();
-    }
+    lint_project(&buildpkg_lua_path(project)?)
+}
 
-    let pkg_dir_value = working_dir.join("pkg");
+fn verify_command(matches: &ArgMatches) -> Result<()> {
+    let package = matches.get_one::<PathBuf>("package").unwrap();
+    let root = matches.get_one::<PathBuf>("root");
 
-    register_lua_functions(&lua, src_dir_value.clone(), pkg_dir_value.clone()).unwrap();
-    register_git_object(&lua, src_dir_value.clone(), pkg_dir_value.clone()).unwrap();
+    // Anything that is not a file is the name of an installed package
+    let (manifest, problems) = if package.is_file() {
+        let (manifest, _, _) = read_metadata_files(package)?;
+        let manifest: Manifest = manifest.parse()?;
 
-    let lua_code = fs::read_to_string(buildpkg_lua).unwrap();
+        let problems = match root {
+            Some(root) => manifest.verify_root(root)?,
+            None => manifest.verify_archive(package)?,
+        };
 
-    let chunk = lua.load(lua_code);
+        (manifest, problems)
+    } else {
+        let root = root.map(PathBuf::as_path).unwrap_or(Path::new("/"));
+        let name = package.to_string_lossy();
 
-    chunk.exec().unwrap();
+        let installed = Database::open(root).get(&name)?
+            .ok_or_else(|| Error::NotInstalled(name.into_owned()))?;
+        let problems = installed.manifest.verify_root(root)?;
 
-    let mut package_info = lua_get_package_info(&lua).unwrap();
+        (installed.manifest, problems)
+    };
 
-    if package_info.dev {
-        println!("Building package in dev mode");
+    for problem in &problems {
+        println!("{}", problem);
     }
 
-    let version_function_exists = lua.globals().get::<Value>("VERSION").is_ok();
-
-    if package_info.version.is_none() && !version_function_exists {
-        eprintln!("Error: version field missing and VERSION function not found");
-        std::process::exit(1);
+    if !problems.is_empty() {
+        return Err(Error::VerificationFailed(problems.len()));
     }
 
-    if package_info.version.is_some() && version_function_exists {
-        eprintln!("Error: version field and VERSION function both found");
-        std::process::exit(1);
-    }
+    println!("{} entries verified", manifest.entries.len());
+
+    Ok(())
+}
 
-    println!("Getting sources...");
-    run_function::<()>(&lua, "SOURCES", ());
+fn install_command(matches: &ArgMatches) -> Result<()> {
+    let root = matches.get_one::<PathBuf>("root").unwrap();
 
-    if package_info.version.is_none() {
-        package_info.version = run_function(&lua, "VERSION", ());
-    }
+    // Existing files are package archives, anything else names a package of the repositories
+    let mut archives = Vec::new();
+    let mut names = Vec::new();
 
-    if package_info.version.is_none() {
-        eprintln!("Error: version field missing and VERSION function returned None");
-        std::process::exit(1);
+    for package in matches.get_many::<String>("packages").unwrap() {
+        if Path::new(package).is_file() {
+            archives.push(PathBuf::from(package));
+        } else {
+            names.push(package.parse::<Dependency>()?);
+        }
     }
 
-    let version = package_info.version.clone().unwrap();
+    let config = Config::load()?;
+    let repositories = load_sync_packages(root, &config.repositories)?;
 
-    // check that version conforms to major.minor.patch-revision format
-    let version_parts: Vec<&str> = version.split('-').collect();
-    if version_parts.len() > 2 {
-        eprintln!("Error: version field does not conform to major.minor.patch-revision format");
-        std::process::exit(1);
-    }
-    let version_semver_parts: Vec<&str> = version_parts[0].split('.').collect();
-    if version_semver_parts.len() != 3 {
-        eprintln!("Error: version field does not conform to major.minor.patch-revision format");
-        std::process::exit(1);
-    }
+    install_packages(root, &archives, &names, &repositories)
+}
 
-    println!("\n- {} {} ({}) maintained by {}\n", package_info.name, package_info.version.clone().unwrap(), package_info.license, package_info.maintainers.join(", "));
+fn sync_command(matches: &ArgMatches) -> Result<()> {
+    let root = matches.get_one::<PathBuf>("root").unwrap();
+    let config = Config::load()?;
 
-    if !package_info.arch.contains(&std::env::consts::ARCH.to_string()) {
-        eprintln!("Error: package not available for host architecture");
-        std::process::exit(1);
-    }
+    sync_repositories(root, &config.repositories)
+}
 
-    println!("Preparing...");
-    run_function::<()>(&lua, "PREPARE", ());
-
-    println!("Packaging...");
-    run_function::<()>(&lua, "PACKAGE", ());
-
-    let mut find_result = Vec::new();
-
-    visit_dirs(&pkg_dir_value, &mut find_result).unwrap();
-
-    fs::write(working_dir.join("pkg").join(".pkgfiles"), find_result.iter().map(|file| {String::from("/") + &String::from(PathBuf::from(file).strip_prefix(&pkg_dir_value).unwrap().to_str().unwrap())}).collect::<Vec<String>>().join("\n")).unwrap();
-
-    let final_package_info: FinalPackageInfo = package_info.into();
-
-    let final_package_info_json = serde_json::to_string(&final_package_info).unwrap();
-    fs::write(working_dir.join("pkg").join("package.json"), final_package_info_json).unwrap();
-
-    // creates a tarball of the pkg directory named after the project version like project-version-arch.tar.gz
-    let tarball_name = format!("{}-{}-{}.tar.gz", final_package_info.name, final_package_info.version.clone(), std::env::consts::ARCH);
-    let tarball_path = working_dir.join(tarball_name);
-    let tar = std::process::Command::new("tar")
-        .arg("--owner=root")
-        .arg("--group=root")
-        .arg("--preserve-permissions")
-        .arg("-czf")
-        .arg(&tarball_path)
-        .arg("-C")
-        .arg("pkg")
-        .arg(".")
-        .current_dir(working_dir.clone().to_str().unwrap())
-        .output()
-        .unwrap();
-    if !tar.status.success() {
-        eprintln!("Error: {}", String::from_utf8_lossy(&tar.stderr));
-        std::process::exit(1);
-    }
+fn upgrade_command(matches: &ArgMatches) -> Result<()> {
+    let packages: Vec<String> = matches.get_many::<String>("packages").unwrap_or_default().cloned().collect();
+    let root = matches.get_one::<PathBuf>("root").unwrap();
 
-    if clean_project_after {
-        if working_dir.join("src").exists() {
-            fs::remove_dir_all(working_dir.join("src")).unwrap();
-        }
+    let config = Config::load()?;
+    let repositories = load_sync_packages(root, &config.repositories)?;
 
-        if working_dir.join("pkg").exists() {
-            fs::remove_dir_all(working_dir.join("pkg")).unwrap();
-        }
-    }
+    upgrade_packages(root, &packages, &repositories, matches.get_flag("dry_run"))
 }
 
-fn visit_dirs(dir: &Path, paths: &mut Vec<String>) -> std::io::Result<()> {
-    if dir.is_dir() {
-        for entry in fs::read_dir(dir)? {
-            let entry = entry?;
-            let path = entry.path();
-            
-            if path.is_file() || path.is_symlink() {
-                // Get relative path from pkg_dir
-                let base_path = Path::new("pkg");
-                if let Some(rel_path) = path.strip_prefix(base_path.parent().unwrap()).ok() {
-                    paths.push(format!("{}", rel_path.display()));
-                }
-            }
-            
-            if path.is_dir() {
-                visit_dirs(&path, paths)?;
-            }
-        }
-    }
-    
-    Ok(())
+fn remove_command(matches: &ArgMatches) -> Result<()> {
+    let packages: Vec<String> = matches.get_many::<String>("packages").unwrap().cloned().collect();
+    let root = matches.get_one::<PathBuf>("root").unwrap();
+
+    remove_packages(root, &packages, matches.get_flag("force"))
 }
 
-fn run_function_if_exists<R: FromLuaMulti>(lua: &Lua, function_name: &str, args: impl IntoLuaMulti) -> Option<R> {
-    let globals = lua.globals();
-    let function_res = globals.get(function_name);
-    if let Err(_) = function_res {
-        return None;
-    }
-    let function: Function = function_res.unwrap();
+fn query_command(matches: &ArgMatches) -> Result<()> {
+    let Some((name, matches)) = matches.subcommand() else {
+        unreachable!("a query subcommand is required");
+    };
+
+    let database = Database::open(matches.get_one::<PathBuf>("root").unwrap());
+    let json = matches.get_flag("json");
 
-    let res = function.call::<R>(args);
-    match res {
-        Ok(r) => Some(r),
-        Err(_) => None,
+    match name {
+        "list" => query::list(&database, json),
+        "info" => query::info(&database, matches.get_one::<String>("package").unwrap(), json),
+        "files" => query::files(&database, matches.get_one::<String>("package").unwrap(), json),
+        "owns" => query::owns(&database, matches.get_one::<PathBuf>("path").unwrap(), json),
+        _ => unreachable!("unknown query subcommand {}", name),
     }
 }
 
-fn run_function<R: FromLuaMulti>(lua: &Lua, function_name: &str, args: impl IntoLuaMulti) -> R {
-    let globals = lua.globals();
-    let function_res = globals.get(function_name);
-    if let Err(_) = function_res {
-        eprintln!("Function {} not found", function_name);
-        process::exit(1);
-    }
-    let function: Function = function_res.unwrap();
-
-    let res = function.call::<R>(args);
-    match res {
-        Ok(r) => r,
-        Err(e) => {
-            eprintln!("Error: {}", e);
-            process::exit(1);
+fn repo_command(matches: &ArgMatches) -> Result<()> {
+    let Some((name, matches)) = matches.subcommand() else {
+        unreachable!("a repo subcommand is required");
+    };
+
+    let repository = matches.get_one::<PathBuf>("repository").unwrap();
+
+    match name {
+        "add" => {
+            let packages: Vec<PathBuf> = matches.get_many::<PathBuf>("packages").unwrap().cloned().collect();
+            repository::repo_add(repository, &packages)
+        }
+        "remove" => {
+            let packages: Vec<String> = matches.get_many::<String>("packages").unwrap().cloned().collect();
+            repository::repo_remove(repository, &packages)
         }
+        "rebuild" => repository::repo_rebuild(repository),
+        _ => unreachable!("unknown repo subcommand {}", name),
     }
 }
+
+fn vercmp_command(matches: &ArgMatches) -> Result<()> {
+    let a: Version = matches.get_one::<String>("a").unwrap().parse()?;
+    let b: Version = matches.get_one::<String>("b").unwrap().parse()?;
+
+    println!("{}", a.cmp(&b) as i8);
+
+    Ok(())
+}
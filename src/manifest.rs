@@ -0,0 +1,494 @@
+use std::{collections::BTreeMap, fmt, fs, io::{self, Read}, os::unix::fs::PermissionsExt, path::Path, str::FromStr};
+use serde::Serialize;
+use sha2::Digest;
+use tar::EntryType;
+
+use crate::archive::{is_metadata_file, open_package_archive};
+use crate::error::{Error, Result};
+
+/// First line of every manifest, identifying the format version
+pub const MANIFEST_HEADER: &str = "#vrdpkg-manifest 1";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    File,
+    Dir,
+    Link,
+}
+
+impl fmt::Display for EntryKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EntryKind::File => write!(f, "file"),
+            EntryKind::Dir => write!(f, "dir"),
+            EntryKind::Link => write!(f, "link"),
+        }
+    }
+}
+
+/// One line of the manifest, describing a single file, directory or symlink of a package
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ManifestEntry {
+    /// Absolute path once installed, e.g. "/usr/bin/zig"
+    pub path: String,
+    #[serde(rename = "type")]
+    pub kind: EntryKind,
+    #[serde(serialize_with = "serialize_mode")]
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    /// Size in bytes, regular files only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// SHA-256 of the contents, regular files only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Target of the symlink, symlinks only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link: Option<String>,
+}
+
+impl ManifestEntry {
+    /// Describe a file on disk, owned by root as it is stored in package archives
+    pub fn from_path(path: &Path, manifest_path: String) -> io::Result<ManifestEntry> {
+        let metadata = fs::symlink_metadata(path)?;
+        let file_type = metadata.file_type();
+
+        let (kind, size, sha256, link) = if file_type.is_symlink() {
+            (EntryKind::Link, None, None, Some(fs::read_link(path)?.to_string_lossy().into_owned()))
+        } else if file_type.is_dir() {
+            (EntryKind::Dir, None, None, None)
+        } else if file_type.is_file() {
+            (EntryKind::File, Some(metadata.len()), Some(sha256_reader(fs::File::open(path)?)?), None)
+        } else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Unsupported file type: {:?}", path)));
+        };
+
+        Ok(ManifestEntry {
+            path: manifest_path,
+            kind,
+            mode: metadata.permissions().mode() & 0o7777,
+            uid: 0,
+            gid: 0,
+            size,
+            sha256,
+            link,
+        })
+    }
+
+    /// Describe an entry read from a package archive
+    fn from_archive_entry<R: Read>(entry: &mut tar::Entry<R>) -> io::Result<ManifestEntry> {
+        let header = entry.header().clone();
+        let path = format!("/{}", entry.path()?.to_string_lossy().trim_end_matches('/'));
+
+        let (kind, size, sha256, link) = match header.entry_type() {
+            EntryType::Symlink => (EntryKind::Link, None, None, entry.link_name()?.map(|link| link.to_string_lossy().into_owned())),
+            EntryType::Directory => (EntryKind::Dir, None, None, None),
+            EntryType::Regular => (EntryKind::File, Some(header.size()?), Some(sha256_reader(entry)?), None),
+            other => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Unsupported entry type {:?} for {}", other, path))),
+        };
+
+        Ok(ManifestEntry {
+            path,
+            kind,
+            mode: header.mode()? & 0o7777,
+            uid: header.uid()? as u32,
+            gid: header.gid()? as u32,
+            size,
+            sha256,
+            link,
+        })
+    }
+
+    /// Compare this (expected) entry with an actual one, describing every mismatch
+    pub fn differences(&self, actual: &ManifestEntry, check_owner: bool) -> Vec<String> {
+        let mut differences = Vec::new();
+
+        if self.kind != actual.kind {
+            differences.push(format!("type changed from {} to {}", self.kind, actual.kind));
+            return differences;
+        }
+
+        if self.mode != actual.mode {
+            differences.push(format!("mode changed from {:04o} to {:04o}", self.mode, actual.mode));
+        }
+
+        if check_owner && (self.uid != actual.uid || self.gid != actual.gid) {
+            differences.push(format!("owner changed from {}:{} to {}:{}", self.uid, self.gid, actual.uid, actual.gid));
+        }
+
+        if self.size != actual.size {
+            differences.push(format!("size changed from {} to {}", self.size.unwrap_or(0), actual.size.unwrap_or(0)));
+        }
+
+        if self.sha256 != actual.sha256 {
+            differences.push("sha256 mismatch".to_string());
+        }
+
+        if self.link != actual.link {
+            differences.push(format!("link target changed from {} to {}", self.link.as_deref().unwrap_or(""), actual.link.as_deref().unwrap_or("")));
+        }
+
+        differences
+    }
+}
+
+impl fmt::Display for ManifestEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} type={} mode={:04o} uid={} gid={}", escape(&self.path), self.kind, self.mode, self.uid, self.gid)?;
+
+        if let Some(size) = self.size {
+            write!(f, " size={}", size)?;
+        }
+
+        if let Some(sha256) = &self.sha256 {
+            write!(f, " sha256={}", sha256)?;
+        }
+
+        if let Some(link) = &self.link {
+            write!(f, " link={}", escape(link))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for ManifestEntry {
+    type Err = String;
+
+    fn from_str(line: &str) -> std::result::Result<Self, Self::Err> {
+        let mut fields = line.split_whitespace();
+        let path = unescape(fields.next().ok_or("empty line")?)?;
+
+        let mut kind = None;
+        let mut mode = None;
+        let mut uid = 0;
+        let mut gid = 0;
+        let mut size = None;
+        let mut sha256 = None;
+        let mut link = None;
+
+        for field in fields {
+            let (key, value) = field.split_once('=')
+                .ok_or_else(|| format!("invalid field \"{}\"", field))?;
+
+            match key {
+                "type" => kind = Some(match value {
+                    "file" => EntryKind::File,
+                    "dir" => EntryKind::Dir,
+                    "link" => EntryKind::Link,
+                    _ => return Err(format!("unknown type \"{}\"", value)),
+                }),
+                "mode" => mode = Some(u32::from_str_radix(value, 8).map_err(|_| format!("invalid mode \"{}\"", value))?),
+                "uid" => uid = value.parse().map_err(|_| format!("invalid uid \"{}\"", value))?,
+                "gid" => gid = value.parse().map_err(|_| format!("invalid gid \"{}\"", value))?,
+                "size" => size = Some(value.parse().map_err(|_| format!("invalid size \"{}\"", value))?),
+                "sha256" => sha256 = Some(value.to_string()),
+                "link" => link = Some(unescape(value)?),
+                _ => return Err(format!("unknown key \"{}\"", key)),
+            }
+        }
+
+        Ok(ManifestEntry {
+            kind: kind.ok_or_else(|| format!("{}: missing type", path))?,
+            mode: mode.ok_or_else(|| format!("{}: missing mode", path))?,
+            path,
+            uid,
+            gid,
+            size,
+            sha256,
+            link,
+        })
+    }
+}
+
+/// Per-file manifest of a package, stored as .pkgfiles in the archive
+///
+/// The format is inspired by mtree: one entry per line, the escaped path followed by key=value
+/// fields, sorted by path.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Manifest {
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    /// Build the manifest of a package directory, leaving out the metadata files
+    pub fn from_dir(pkg_dir: &Path) -> io::Result<Manifest> {
+        let mut entries = Vec::new();
+
+        for entry in walkdir::WalkDir::new(pkg_dir).min_depth(1).sort_by_file_name() {
+            let entry = entry?;
+            let relative_path = entry.path().strip_prefix(pkg_dir)
+                .map_err(io::Error::other)?;
+
+            if is_metadata_file(relative_path) {
+                continue;
+            }
+
+            entries.push(ManifestEntry::from_path(entry.path(), format!("/{}", relative_path.display()))?);
+        }
+
+        Ok(Manifest { entries })
+    }
+
+    /// Check the package archive the manifest was read from, returning every mismatch
+    pub fn verify_archive(&self, archive_path: &Path) -> Result<Vec<String>> {
+        let mut expected: BTreeMap<&str, &ManifestEntry> = self.entries.iter()
+            .map(|entry| (entry.path.as_str(), entry))
+            .collect();
+        let mut problems = Vec::new();
+
+        let mut archive = open_package_archive(archive_path)?;
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+
+            if is_metadata_file(&entry.path()?) {
+                continue;
+            }
+
+            let actual = ManifestEntry::from_archive_entry(&mut entry)?;
+
+            match expected.remove(actual.path.as_str()) {
+                Some(expected) => problems.extend(expected.differences(&actual, true).into_iter()
+                    .map(|difference| format!("{}: {}", actual.path, difference))),
+                None => problems.push(format!("{}: not listed in the manifest", actual.path)),
+            }
+        }
+
+        problems.extend(expected.keys().map(|path| format!("{}: missing from the archive", path)));
+
+        Ok(problems)
+    }
+
+    /// Check the files installed under root, returning every local change
+    ///
+    /// Ownership is not compared as unprivileged installs cannot preserve it.
+    pub fn verify_root(&self, root: &Path) -> Result<Vec<String>> {
+        let mut problems = Vec::new();
+
+        for expected in &self.entries {
+            let path = root.join(expected.path.trim_start_matches('/'));
+
+            match ManifestEntry::from_path(&path, expected.path.clone()) {
+                Ok(actual) => problems.extend(expected.differences(&actual, false).into_iter()
+                    .map(|difference| format!("{}: {}", expected.path, difference))),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => problems.push(format!("{}: missing", expected.path)),
+                Err(e) => problems.push(format!("{}: {}", expected.path, e)),
+            }
+        }
+
+        Ok(problems)
+    }
+}
+
+impl fmt::Display for Manifest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", MANIFEST_HEADER)?;
+
+        for entry in &self.entries {
+            writeln!(f, "{}", entry)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Manifest {
+    type Err = Error;
+
+    fn from_str(content: &str) -> Result<Self> {
+        let mut lines = content.lines();
+
+        if lines.next() != Some(MANIFEST_HEADER) {
+            return Err(Error::Manifest("missing manifest header".to_string()));
+        }
+
+        let entries = lines
+            .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+            .map(|line| line.parse::<ManifestEntry>().map_err(Error::Manifest))
+            .collect::<Result<Vec<ManifestEntry>>>()?;
+
+        Ok(Manifest { entries })
+    }
+}
+
+/// Serialize a mode the way it is written in manifests, as an octal string
+fn serialize_mode<S: serde::Serializer>(mode: &u32, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!("{:04o}", mode))
+}
+
+/// Calculate the SHA-256 of everything read from a reader
+fn sha256_reader(mut reader: impl Read) -> io::Result<String> {
+    let mut hasher = sha2::Sha256::new();
+    io::copy(&mut reader, &mut hasher)?;
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Escape whitespace, control characters and backslashes as \ooo octal sequences
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        if c == '\\' || c.is_whitespace() || c.is_control() {
+            let mut buffer = [0u8; 4];
+            for byte in c.encode_utf8(&mut buffer).bytes() {
+                escaped.push_str(&format!("\\{:03o}", byte));
+            }
+        } else {
+            escaped.push(c);
+        }
+    }
+
+    escaped
+}
+
+fn unescape(value: &str) -> std::result::Result<String, String> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut input = value.bytes();
+
+    while let Some(byte) = input.next() {
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+
+        let digits: Vec<u8> = input.by_ref().take(3).collect();
+        let octal = std::str::from_utf8(&digits).ok()
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok())
+            .filter(|_| digits.len() == 3)
+            .ok_or_else(|| format!("invalid escape sequence in \"{}\"", value))?;
+
+        bytes.push(octal);
+    }
+
+    String::from_utf8(bytes).map_err(|_| format!("invalid UTF-8 in \"{}\"", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, os::unix::fs::{symlink, PermissionsExt}};
+
+    use super::{escape, unescape, EntryKind, Manifest, ManifestEntry};
+    use crate::archive::{create_package_archive, ArchiveOptions};
+
+    /// Paths with whitespace, newlines, backslashes and non-ASCII characters
+    const NAMES: [&str; 6] = ["plain", "with space", "new\nline", "back\\slash", "tab\tand\u{7f}", "ünïcödé 日本"];
+
+    #[test]
+    fn escaping() {
+        let cases = [
+            ("/usr/bin/zig", "/usr/bin/zig"),
+            ("/a b", "/a\\040b"),
+            ("/a\nb", "/a\\012b"),
+            ("/a\\b", "/a\\134b"),
+            ("/é", "/é"),
+            ("/a\u{a0}b", "/a\\302\\240b"),
+        ];
+
+        for (value, escaped) in cases {
+            assert_eq!(escape(value), escaped, "{:?}", value);
+            assert_eq!(unescape(escaped).as_deref(), Ok(value), "{:?}", escaped);
+        }
+
+        for escaped in ["a\\", "a\\04", "a\\0x1", "a\\400", "a\\377"] {
+            assert!(unescape(escaped).is_err(), "{:?}", escaped);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+
+        for name in NAMES {
+            let subdir = dir.path().join(name);
+            fs::create_dir(&subdir).unwrap();
+            fs::write(subdir.join(name), name).unwrap();
+            symlink(format!("../{}/{}", name, name), subdir.join(format!("{} link", name))).unwrap();
+        }
+
+        fs::set_permissions(dir.path().join("plain/plain"), fs::Permissions::from_mode(0o4755)).unwrap();
+
+        let manifest = Manifest::from_dir(dir.path()).unwrap();
+        let content = manifest.to_string();
+
+        assert_eq!(manifest.entries.len(), NAMES.len() * 3);
+        assert_eq!(content.lines().count(), manifest.entries.len() + 1);
+        assert_eq!(content.parse::<Manifest>().unwrap(), manifest);
+
+        let entry = manifest.entries.iter().find(|entry| entry.path == "/with space/with space link").unwrap();
+        assert_eq!(entry.to_string(), "/with\\040space/with\\040space\\040link type=link mode=0777 uid=0 gid=0 link=../with\\040space/with\\040space");
+    }
+
+    #[test]
+    fn parse() {
+        let entry: ManifestEntry = "/etc/a\\040b type=file mode=0640 uid=0 gid=42 size=3 sha256=abc".parse().unwrap();
+
+        assert_eq!(entry, ManifestEntry {
+            path: "/etc/a b".to_string(),
+            kind: EntryKind::File,
+            mode: 0o640,
+            uid: 0,
+            gid: 42,
+            size: Some(3),
+            sha256: Some("abc".to_string()),
+            link: None,
+        });
+
+        let cases = [
+            "",
+            "/a mode=0755",
+            "/a type=dir",
+            "/a type=fifo mode=0755",
+            "/a type=dir mode=0999",
+            "/a type=dir mode=0755 owner=root",
+            "/a type=dir mode=0755 uid",
+        ];
+
+        for line in cases {
+            assert!(line.parse::<ManifestEntry>().is_err(), "{:?}", line);
+        }
+
+        assert!("/a type=dir mode=0755\n".parse::<Manifest>().is_err());
+        assert_eq!("#vrdpkg-manifest 1\n\n# comment\n/a type=dir mode=0755\n".parse::<Manifest>().unwrap().entries.len(), 1);
+    }
+
+    #[test]
+    fn verify_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let pkg_dir = dir.path().join("pkg");
+
+        for name in NAMES {
+            fs::create_dir_all(pkg_dir.join("usr/share")).unwrap();
+            fs::write(pkg_dir.join("usr/share").join(name), name).unwrap();
+        }
+
+        let manifest = Manifest::from_dir(&pkg_dir).unwrap();
+        fs::write(pkg_dir.join(".pkgfiles"), manifest.to_string()).unwrap();
+        fs::write(pkg_dir.join("package.json"), "{}").unwrap();
+
+        let archive = dir.path().join("pkg.tar.zst");
+        create_package_archive(&pkg_dir, &archive, &ArchiveOptions::default()).unwrap();
+        assert_eq!(manifest.verify_archive(&archive).unwrap(), Vec::<String>::new());
+
+        // Same size, other contents, then a file left out and one added
+        fs::write(pkg_dir.join("usr/share/plain"), "PLAIN").unwrap();
+        fs::set_permissions(pkg_dir.join("usr/share/new\nline"), fs::Permissions::from_mode(0o600)).unwrap();
+        fs::remove_file(pkg_dir.join("usr/share/with space")).unwrap();
+        fs::write(pkg_dir.join("usr/share/extra"), "").unwrap();
+
+        create_package_archive(&pkg_dir, &archive, &ArchiveOptions::default()).unwrap();
+
+        let mut problems = manifest.verify_archive(&archive).unwrap();
+        problems.sort();
+
+        assert_eq!(problems, [
+            "/usr/share/extra: not listed in the manifest",
+            "/usr/share/new\nline: mode changed from 0644 to 0600",
+            "/usr/share/plain: sha256 mismatch",
+            "/usr/share/with space: missing from the archive",
+        ]);
+    }
+}
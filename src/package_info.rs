@@ -0,0 +1,400 @@
+use std::{collections::BTreeMap, path::{Path, PathBuf}, str::FromStr};
+use mlua::{FromLua, Lua, Table, Value};
+use serde::{Deserialize, Serialize};
+
+use crate::archive::read_metadata_files;
+use crate::dependency::{validate_name, Dependency, Provide};
+use crate::error::{Error, Result};
+use crate::manifest::{EntryKind, Manifest};
+use crate::qa::{QaCheck, QaLevel};
+use crate::source::{get_sources, Source};
+use crate::version::Version;
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct PackageInfo {
+    pub name: String,
+    pub description: String,
+    pub version: Option<String>,
+    pub license: String,
+    pub dev: bool,
+    pub dependencies: Vec<Dependency>,
+    pub build_dependencies: Vec<Dependency>,
+    pub optional_dependencies: Vec<String>,
+    pub conflicts: Vec<Dependency>,
+    pub provides: Vec<Provide>,
+    pub replaces: Vec<Dependency>,
+    pub backup: Vec<String>,
+    /// Lua file of the project defining the install hooks (PRE_INSTALL, POST_INSTALL, ...)
+    pub install: Option<String>,
+    /// Files fetched into SRC_DIR before PREPARE, only set on INFO
+    pub sources: Vec<Source>,
+    /// Strip the ELF files of the package, moving their debug info into a <name>-debug package
+    pub strip: bool,
+    /// Globs of absolute paths left unstripped, e.g. "/usr/lib/firmware/**"
+    pub no_strip: Vec<String>,
+    /// Levels of the package checks, overriding the configuration file
+    pub qa: BTreeMap<QaCheck, QaLevel>,
+    pub arch: Vec<String>,
+    pub url: String,
+    pub maintainers: Vec<String>,
+}
+
+/// A configuration file of the package, kept when the user has edited it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BackupFile {
+    /// Absolute path once installed, e.g. "/etc/zig.conf"
+    pub path: String,
+    /// SHA-256 of the packaged contents
+    pub sha256: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FinalPackageInfo {
+    pub name: String,
+    pub description: String,
+    pub version: Version,
+    pub license: String,
+    pub dev: bool,
+    pub dependencies: Vec<Dependency>,
+    pub conflicts: Vec<Dependency>,
+    pub provides: Vec<Provide>,
+    pub replaces: Vec<Dependency>,
+    /// Packages built before backup lists existed have none
+    #[serde(default)]
+    pub backup: Vec<BackupFile>,
+    pub arch: Vec<String>,
+    pub url: String,
+    pub maintainers: Vec<String>,
+}
+
+impl FinalPackageInfo {
+    /// Complete the INFO table of a packaged project, hashing its backup files from the manifest
+    pub fn new(info: PackageInfo, manifest: &Manifest) -> Result<Self> {
+        let version = info.version
+            .ok_or_else(|| Error::InvalidPackage("version field missing".to_string()))?
+            .parse()?;
+
+        let backup = info.backup.into_iter()
+            .map(|path| {
+                let entry = manifest.entries.iter().find(|entry| entry.path == path);
+
+                match entry.and_then(|entry| entry.sha256.clone().filter(|_| entry.kind == EntryKind::File)) {
+                    Some(sha256) => Ok(BackupFile { path, sha256 }),
+                    None => Err(Error::InvalidPackage(format!("backup field: {} is not a file of the package", path))),
+                }
+            })
+            .collect::<Result<Vec<BackupFile>>>()?;
+
+        Ok(FinalPackageInfo {
+            name: info.name,
+            description: info.description,
+            version,
+            license: info.license,
+            dev: info.dev,
+            dependencies: info.dependencies,
+            conflicts: info.conflicts,
+            provides: info.provides,
+            replaces: info.replaces,
+            backup,
+            arch: info.arch,
+            url: info.url,
+            maintainers: info.maintainers,
+        })
+    }
+
+    /// "name version", as shown to users
+    pub fn display_name(&self) -> String {
+        format!("{} {}", self.name, self.version)
+    }
+}
+
+#[cfg(test)]
+impl FinalPackageInfo {
+    /// A package for the host architecture with nothing but a name and version, the other fields
+    /// are set with struct update syntax
+    pub fn bare(name: &str, version: &str) -> Self {
+        FinalPackageInfo {
+            name: name.to_string(),
+            description: String::new(),
+            version: version.parse().unwrap_or_else(|e| panic!("{}: {}", version, e)),
+            license: "MIT".to_string(),
+            dev: false,
+            dependencies: Vec::new(),
+            conflicts: Vec::new(),
+            provides: Vec::new(),
+            replaces: Vec::new(),
+            backup: Vec::new(),
+            arch: vec![std::env::consts::ARCH.to_string()],
+            url: String::new(),
+            maintainers: Vec::new(),
+        }
+    }
+}
+
+/// A built package archive along with the metadata stored at its start
+pub struct PackageArchive {
+    pub path: PathBuf,
+    pub info: FinalPackageInfo,
+    pub manifest: Manifest,
+    /// Install scriptlets (.INSTALL)
+    pub install: Option<String>,
+}
+
+impl PackageArchive {
+    /// Read the package.json and manifest of a package archive
+    pub fn open(path: &Path) -> Result<PackageArchive> {
+        let (manifest, package_json, install) = read_metadata_files(path)?;
+        let info: FinalPackageInfo = serde_json::from_str(&package_json)?;
+
+        validate_name(&info.name)
+            .map_err(|e| Error::InvalidPackage(format!("{:?}: {}", path, e)))?;
+
+        Ok(PackageArchive {
+            path: path.to_path_buf(),
+            info,
+            manifest: manifest.parse()?,
+            install,
+        })
+    }
+}
+
+/// Type of a field of INFO
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+    String,
+    Boolean,
+    /// A list of strings
+    List,
+    /// A list of tables
+    TableList,
+    Table,
+}
+
+/// Every field of INFO, with its type and whether it is required
+pub const INFO_FIELDS: [(&str, FieldType, bool); 21] = [
+    ("name", FieldType::String, true),
+    ("description", FieldType::String, true),
+    ("version", FieldType::String, false),
+    ("license", FieldType::String, true),
+    ("dev", FieldType::Boolean, true),
+    ("url", FieldType::String, true),
+    ("maintainers", FieldType::List, true),
+    ("provides", FieldType::List, true),
+    ("arch", FieldType::List, true),
+    ("dependencies", FieldType::List, false),
+    ("build_dependencies", FieldType::List, false),
+    ("optional_dependencies", FieldType::List, false),
+    ("conflicts", FieldType::List, false),
+    ("replaces", FieldType::List, false),
+    ("backup", FieldType::List, false),
+    ("install", FieldType::String, false),
+    ("strip", FieldType::Boolean, false),
+    ("no_strip", FieldType::List, false),
+    ("qa", FieldType::Table, false),
+    ("subpackages", FieldType::TableList, false),
+    ("sources", FieldType::TableList, false),
+];
+
+/// Read a required field from the INFO table
+fn get_field<T: FromLua>(info_table: &Table, field: &str) -> Result<T> {
+    match info_table.get::<Option<T>>(field) {
+        Ok(Some(value)) => Ok(value),
+        Ok(None) => Err(Error::InvalidPackage(format!("{} field missing", field))),
+        Err(_) => Err(Error::InvalidPackage(format!("{} field has the wrong type", field))),
+    }
+}
+
+/// Read a list of strings from the INFO table, optional lists default to being empty
+fn get_string_list(info_table: &Table, field: &str, required: bool) -> Result<Vec<String>> {
+    let table = match info_table.get::<Option<Table>>(field) {
+        Ok(Some(table)) => table,
+        Ok(None) if !required => return Ok(Vec::new()),
+        Ok(None) => return Err(Error::InvalidPackage(format!("{} field missing", field))),
+        Err(_) => return Err(Error::InvalidPackage(format!("{} field must be a list of strings", field))),
+    };
+
+    table.sequence_values::<String>()
+        .collect::<mlua::Result<Vec<String>>>()
+        .map_err(|_| Error::InvalidPackage(format!("{} field must be a list of strings", field)))
+}
+
+/// Read a list of dependencies, provides or other parsed strings from the INFO table
+fn get_parsed_list<T: FromStr<Err = Error>>(info_table: &Table, field: &str, required: bool) -> Result<Vec<T>> {
+    get_string_list(info_table, field, required)?
+        .iter()
+        .map(|value| value.parse::<T>())
+        .collect()
+}
+
+/// Read the qa field of INFO, a table of check names to levels
+fn get_qa_levels(info_table: &Table) -> Result<BTreeMap<QaCheck, QaLevel>> {
+    let table = match info_table.get::<Option<Table>>("qa") {
+        Ok(Some(table)) => table,
+        Ok(None) => return Ok(BTreeMap::new()),
+        Err(_) => return Err(Error::InvalidPackage("qa field must be a table of check names to levels".to_string())),
+    };
+
+    table.pairs::<String, String>()
+        .map(|pair| {
+            let (check, level) = pair.map_err(|_| Error::InvalidPackage("qa field must be a table of check names to levels".to_string()))?;
+            let invalid = |e: String| Error::InvalidPackage(format!("qa field: {}", e));
+
+            Ok((check.parse().map_err(invalid)?, level.parse().map_err(invalid)?))
+        })
+        .collect()
+}
+
+pub fn lua_get_package_info(lua: &Lua) -> Result<PackageInfo> {
+    package_info_from_table(&info_table(lua)?, None)
+}
+
+/// Read the split packages listed in the subpackages field of INFO
+///
+/// Subpackages share the version of INFO and inherit its description, url, license, dev,
+/// maintainers, arch, strip and qa fields unless they set them. Relations (dependencies, provides,
+/// conflicts, replaces), backup, no_strip and install are their own, sources belong to INFO only.
+pub fn lua_get_subpackages(lua: &Lua, parent: &PackageInfo) -> Result<Vec<PackageInfo>> {
+    let info_table = info_table(lua)?;
+
+    let subpackages = match info_table.get::<Option<Table>>("subpackages") {
+        Ok(Some(subpackages)) => subpackages,
+        Ok(None) => return Ok(Vec::new()),
+        Err(_) => return Err(Error::InvalidPackage("subpackages field must be a list of tables".to_string())),
+    };
+
+    let mut packages: Vec<PackageInfo> = Vec::new();
+
+    for table in subpackages.sequence_values::<Table>() {
+        let table = table.map_err(|_| Error::InvalidPackage("subpackages field must be a list of tables".to_string()))?;
+        let package = package_info_from_table(&table, Some(parent)).map_err(|e| match e {
+            Error::InvalidPackage(message) => Error::InvalidPackage(format!("subpackage {}: {}", table.get::<String>("name").unwrap_or_default(), message)),
+            e => e,
+        })?;
+
+        if package.name == parent.name || packages.iter().any(|other| other.name == package.name) {
+            return Err(Error::InvalidPackage(format!("subpackages field: {} is listed twice", package.name)));
+        }
+
+        packages.push(package);
+    }
+
+    Ok(packages)
+}
+
+/// Get the INFO table
+pub fn info_table(lua: &Lua) -> Result<Table> {
+    lua.globals().get::<Option<Table>>("INFO")
+        .ok()
+        .flatten()
+        .ok_or_else(|| Error::InvalidPackage("INFO table missing".to_string()))
+}
+
+/// Read INFO, or a subpackage table inheriting from the parent package
+fn package_info_from_table(info_table: &Table, parent: Option<&PackageInfo>) -> Result<PackageInfo> {
+    // Subpackages fall back to the parent for the fields they leave out
+    let inherited = |field: &str| parent.filter(|_| matches!(info_table.get::<Value>(field), Ok(Value::Nil)));
+
+    // Required fields
+    let name: String = get_field(info_table, "name")?;
+    let description = match inherited("description") {
+        Some(parent) => parent.description.clone(),
+        None => get_field(info_table, "description")?,
+    };
+    let url = match inherited("url") {
+        Some(parent) => parent.url.clone(),
+        None => get_field(info_table, "url")?,
+    };
+    let license = match inherited("license") {
+        Some(parent) => parent.license.clone(),
+        None => get_field(info_table, "license")?,
+    };
+    let dev = match inherited("dev") {
+        Some(parent) => parent.dev,
+        None => get_field(info_table, "dev")?,
+    };
+
+    // Optional strip field, stripping by default
+    let strip = match inherited("strip") {
+        Some(parent) => parent.strip,
+        None => match info_table.get::<Value>("strip") {
+            Ok(Value::Nil) => true,
+            Ok(Value::Boolean(strip)) => strip,
+            _ => return Err(Error::InvalidPackage("strip field must be a boolean".to_string())),
+        },
+    };
+
+    validate_name(&name).map_err(|e| Error::InvalidPackage(format!("name field: {}", e)))?;
+
+    // Optional version field (can be None)
+    let version: Option<String> = match parent {
+        Some(_) if inherited("version").is_none() => return Err(Error::InvalidPackage("version field is shared with INFO".to_string())),
+        Some(parent) => parent.version.clone(),
+        None => info_table.get("version")
+            .map_err(|_| Error::InvalidPackage("version field must be a string".to_string()))?,
+    };
+
+    let install: Option<String> = info_table.get("install")
+        .map_err(|_| Error::InvalidPackage("install field must be a string".to_string()))?;
+
+    let sources = match parent {
+        Some(_) if inherited("sources").is_none() => return Err(Error::InvalidPackage("sources field belongs to INFO".to_string())),
+        Some(_) => Vec::new(),
+        None => get_sources(info_table)?,
+    };
+
+    let qa = match inherited("qa") {
+        Some(parent) => parent.qa.clone(),
+        None => get_qa_levels(info_table)?,
+    };
+
+    // Required array fields
+    let provides = get_parsed_list(info_table, "provides", parent.is_none())?;
+    let arch = match inherited("arch") {
+        Some(parent) => parent.arch.clone(),
+        None => get_string_list(info_table, "arch", true)?,
+    };
+    let maintainers = match inherited("maintainers") {
+        Some(parent) => parent.maintainers.clone(),
+        None => get_string_list(info_table, "maintainers", true)?,
+    };
+
+    // Optional array fields - initialize as empty arrays if missing
+    let dependencies = get_parsed_list(info_table, "dependencies", false)?;
+    let build_dependencies = get_parsed_list(info_table, "build_dependencies", false)?;
+    let optional_dependencies = get_string_list(info_table, "optional_dependencies", false)?;
+    let conflicts = get_parsed_list(info_table, "conflicts", false)?;
+    let replaces = get_parsed_list(info_table, "replaces", false)?;
+    let backup = get_string_list(info_table, "backup", false)?;
+    let no_strip = get_string_list(info_table, "no_strip", false)?;
+
+    if let Some(path) = backup.iter().find(|path| !path.starts_with('/')) {
+        return Err(Error::InvalidPackage(format!("backup field: {} is not an absolute path", path)));
+    }
+
+    if let Some(glob) = no_strip.iter().find(|glob| !glob.starts_with('/')) {
+        return Err(Error::InvalidPackage(format!("no_strip field: {} is not an absolute path", glob)));
+    }
+
+    Ok(PackageInfo {
+        name,
+        description,
+        version,
+        license,
+        dev,
+        dependencies,
+        build_dependencies,
+        optional_dependencies,
+        conflicts,
+        provides,
+        replaces,
+        backup,
+        install,
+        sources,
+        strip,
+        no_strip,
+        qa,
+        arch,
+        url,
+        maintainers,
+    })
+}
@@ -1,4 +1,4 @@
-use std::path::{Path, PathBuf};
+use std::{ffi::OsString, fs, path::{Component, Path, PathBuf}};
 use thiserror::Error;
 use path_clean::PathClean;
 
@@ -52,3 +52,86 @@ pub fn validate_absolute_path(path: &Path) -> Result<PathBuf, PathError> {
     
     Ok(path.to_path_buf())
 }
+
+/// Maximum number of symlinks followed while resolving a path inside a root
+const MAX_SYMLINKS: usize = 40;
+
+/// Resolves a path the way it would be seen from inside root, as if root was chrooted into
+///
+/// Symlinks are followed with absolute targets restarting from root and ".." never leaving it,
+/// so the result always stays within root.
+pub fn resolve_in_root(root: &Path, path: &Path) -> Result<PathBuf, PathError> {
+    let mut resolved = PathBuf::new();
+    let mut pending: Vec<OsString> = Vec::new();
+    let mut symlinks = 0;
+
+    push_components(&mut pending, path);
+
+    while let Some(component) = pending.pop() {
+        if component == ".." {
+            resolved.pop();
+            continue;
+        }
+
+        let candidate = root.join(&resolved).join(&component);
+
+        match fs::symlink_metadata(&candidate) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                symlinks += 1;
+
+                if symlinks > MAX_SYMLINKS {
+                    return Err(PathError::InvalidPath(format!("Too many levels of symbolic links: {:?}", path)));
+                }
+
+                let target = fs::read_link(&candidate)
+                    .map_err(|e| PathError::InvalidPath(format!("{:?}: {}", candidate, e)))?;
+
+                if target.is_absolute() {
+                    resolved.clear();
+                }
+
+                push_components(&mut pending, &target);
+            }
+            _ => resolved.push(component),
+        }
+    }
+
+    Ok(root.join(resolved))
+}
+
+/// Pushes the components of a path onto a stack so that the first component is popped first
+fn push_components(stack: &mut Vec<OsString>, path: &Path) {
+    let components: Vec<OsString> = path.components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_os_string()),
+            Component::ParentDir => Some(OsString::from("..")),
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => None,
+        })
+        .collect();
+
+    stack.extend(components.into_iter().rev());
+}
+
+/// Match a path against a glob: `*` and `?` stay inside a directory, `**/` matches any number of
+/// directories and a trailing `**` matches everything below
+pub fn glob_match(glob: &str, path: &str) -> bool {
+    glob_match_bytes(glob.as_bytes(), path.as_bytes())
+}
+
+fn glob_match_bytes(glob: &[u8], path: &[u8]) -> bool {
+    match glob {
+        [] => path.is_empty(),
+        [b'*', b'*'] => true,
+        [b'*', b'*', b'/', rest @ ..] => {
+            // Zero or more whole directories
+            glob_match_bytes(rest, path) || path.iter().enumerate()
+                .any(|(i, &c)| c == b'/' && glob_match_bytes(rest, &path[i + 1..]))
+        }
+        [b'*', rest @ ..] => {
+            let end = path.iter().position(|&c| c == b'/').unwrap_or(path.len());
+            (0..=end).any(|i| glob_match_bytes(rest, &path[i..]))
+        }
+        [b'?', rest @ ..] => matches!(path, [c, tail @ ..] if *c != b'/' && glob_match_bytes(rest, tail)),
+        [c, rest @ ..] => matches!(path, [p, tail @ ..] if p == c && glob_match_bytes(rest, tail)),
+    }
+}
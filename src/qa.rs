@@ -0,0 +1,306 @@
+use std::{collections::{BTreeMap, HashSet}, fmt, fs, io, os::unix::fs::PermissionsExt, path::Path, str::FromStr};
+use serde::{Deserialize, Serialize};
+
+use crate::archive::is_metadata_file;
+use crate::elf::elf_files;
+use crate::error::Result;
+use crate::path_utils::resolve_in_root;
+
+/// A check of the package directory, run before it is archived
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum QaCheck {
+    /// Files under /usr/local or /home, which belong to the administrator and the users
+    MisplacedPath,
+    WorldWritable,
+    /// Symlinks pointing to nothing in the packages of the build or installed under the root
+    DanglingSymlink,
+    /// The build directory (and so SRC_DIR) written into binaries or pkg-config files
+    BuildPath,
+    EmptyDir,
+    /// Nothing under /usr/share/licenses/<name>
+    MissingLicense,
+}
+
+impl QaCheck {
+    pub const ALL: [QaCheck; 6] = [
+        QaCheck::MisplacedPath,
+        QaCheck::WorldWritable,
+        QaCheck::DanglingSymlink,
+        QaCheck::BuildPath,
+        QaCheck::EmptyDir,
+        QaCheck::MissingLicense,
+    ];
+
+    /// Name used in the configuration file and the qa field of INFO
+    pub fn name(self) -> &'static str {
+        match self {
+            QaCheck::MisplacedPath => "misplaced-path",
+            QaCheck::WorldWritable => "world-writable",
+            QaCheck::DanglingSymlink => "dangling-symlink",
+            QaCheck::BuildPath => "build-path",
+            QaCheck::EmptyDir => "empty-dir",
+            QaCheck::MissingLicense => "missing-license",
+        }
+    }
+}
+
+impl FromStr for QaCheck {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        QaCheck::ALL.into_iter()
+            .find(|check| check.name() == s)
+            .ok_or_else(|| format!("unknown check \"{}\" (expected {})", s, QaCheck::ALL.map(QaCheck::name).join(", ")))
+    }
+}
+
+impl TryFrom<String> for QaCheck {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<QaCheck> for String {
+    fn from(check: QaCheck) -> Self {
+        check.name().to_string()
+    }
+}
+
+impl fmt::Display for QaCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// What a failed check does to the build
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum QaLevel {
+    Ignore,
+    #[default]
+    Warn,
+    Error,
+}
+
+impl FromStr for QaLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ignore" => Ok(QaLevel::Ignore),
+            "warn" => Ok(QaLevel::Warn),
+            "error" => Ok(QaLevel::Error),
+            _ => Err(format!("unknown level \"{}\" (expected ignore, warn or error)", s)),
+        }
+    }
+}
+
+impl TryFrom<String> for QaLevel {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, String> {
+        value.parse()
+    }
+}
+
+impl From<QaLevel> for String {
+    fn from(level: QaLevel) -> Self {
+        match level {
+            QaLevel::Ignore => "ignore",
+            QaLevel::Warn => "warn",
+            QaLevel::Error => "error",
+        }.to_string()
+    }
+}
+
+/// Run the checks of a package directory, printing the problems of the checks set to warn and
+/// returning those of the checks set to error
+///
+/// Checks missing from levels warn.
+pub fn check_package(name: &str, pkg_dir: &Path, build_dir: &Path, levels: &BTreeMap<QaCheck, QaLevel>, link_targets: &LinkTargets) -> Result<Vec<String>> {
+    let level = |check: QaCheck| levels.get(&check).copied().unwrap_or_default();
+    let mut problems: Vec<(QaCheck, String)> = Vec::new();
+
+    for entry in walkdir::WalkDir::new(pkg_dir).min_depth(1).sort_by_file_name() {
+        let entry = entry.map_err(io::Error::from)?;
+        let relative_path = entry.path().strip_prefix(pkg_dir)
+            .map_err(io::Error::other)?;
+
+        if is_metadata_file(relative_path) {
+            continue;
+        }
+
+        let path = format!("/{}", relative_path.display());
+        let file_type = entry.file_type();
+
+        let misplaced = ["/usr/local", "/home"].iter()
+            .find(|dir| path.strip_prefix(**dir).is_some_and(|rest| rest.starts_with('/')));
+
+        if let Some(dir) = misplaced && !file_type.is_dir() {
+            problems.push((QaCheck::MisplacedPath, format!("{} is under {}", path, dir)));
+        }
+
+        if file_type.is_symlink() {
+            let target = fs::read_link(entry.path())?;
+
+            // Relative targets start from the directory of the link, links that never reach a
+            // target are dangling too
+            let Ok(resolved) = resolve_in_root(pkg_dir, &Path::new(&path).parent().unwrap_or(Path::new("/")).join(&target)) else {
+                problems.push((QaCheck::DanglingSymlink, format!("{} is a symlink loop", path)));
+                continue;
+            };
+            let resolved = Path::new("/").join(resolved.strip_prefix(pkg_dir).map_err(io::Error::other)?);
+
+            if !link_targets.contains(&resolved) {
+                problems.push((QaCheck::DanglingSymlink, format!("{} points to missing {}", path, target.display())));
+            }
+
+            continue;
+        }
+
+        let mode = entry.metadata().map_err(io::Error::from)?.permissions().mode();
+
+        // Sticky directories such as /tmp are meant to be shared
+        if mode & 0o002 != 0 && !(file_type.is_dir() && mode & 0o1000 != 0) {
+            problems.push((QaCheck::WorldWritable, format!("{} is world-writable", path)));
+        }
+
+        if file_type.is_dir() && fs::read_dir(entry.path())?.next().is_none() {
+            problems.push((QaCheck::EmptyDir, format!("{} is an empty directory", path)));
+        }
+
+        if file_type.is_file() && path.ends_with(".pc") && level(QaCheck::BuildPath) != QaLevel::Ignore {
+            problems.extend(find_build_path(entry.path(), &path, build_dir)?);
+        }
+    }
+
+    if level(QaCheck::BuildPath) != QaLevel::Ignore {
+        for elf_file in elf_files(pkg_dir)? {
+            problems.extend(find_build_path(&elf_file.file, &elf_file.path, build_dir)?);
+        }
+    }
+
+    let licenses = pkg_dir.join("usr/share/licenses").join(name);
+
+    if fs::read_dir(&licenses).map_or(true, |mut entries| entries.next().is_none()) {
+        problems.push((QaCheck::MissingLicense, format!("no license file under /usr/share/licenses/{}", name)));
+    }
+
+    let mut errors = Vec::new();
+
+    for (check, message) in problems {
+        match level(check) {
+            QaLevel::Ignore => {}
+            QaLevel::Warn => eprintln!("Warning: {}: {} [{}]", name, message, check),
+            QaLevel::Error => errors.push(format!("{}: {} [{}]", name, message, check)),
+        }
+    }
+
+    Ok(errors)
+}
+
+/// What the symlinks of a package may point to, so the checks do not depend on the build host
+pub struct LinkTargets<'a> {
+    /// Package directories of the build, split and debug packages included
+    pub pkg_dirs: Vec<&'a Path>,
+    /// Root the package is built against
+    pub root: &'a Path,
+    /// Paths of the packages installed under root
+    pub installed: HashSet<&'a str>,
+}
+
+impl LinkTargets<'_> {
+    /// Returns true if an absolute path exists in a package of the build or is installed, following
+    /// the symlinks of each
+    fn contains(&self, path: &Path) -> bool {
+        let in_build = self.pkg_dirs.iter()
+            .any(|pkg_dir| resolve_in_root(pkg_dir, path).is_ok_and(|resolved| resolved.exists()));
+
+        // Installed symlinks, e.g. /lib pointing to usr/lib, are followed on disk
+        let installed = resolve_in_root(self.root, path).ok()
+            .and_then(|resolved| resolved.strip_prefix(self.root).ok().map(|resolved| format!("/{}", resolved.display())))
+            .is_some_and(|resolved| self.installed.contains(resolved.as_str()));
+
+        in_build || installed
+    }
+}
+
+/// Look for the build directory inside a file of the package
+fn find_build_path(file: &Path, path: &str, build_dir: &Path) -> Result<Option<(QaCheck, String)>> {
+    let contents = fs::read(file)?;
+    let build_dir = build_dir.as_os_str().as_encoded_bytes();
+
+    Ok(contents.windows(build_dir.len())
+        .any(|window| window == build_dir)
+        .then(|| (QaCheck::BuildPath, format!("{} references the build directory {}", path, String::from_utf8_lossy(build_dir)))))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::{BTreeMap, HashSet}, fs, os::unix::fs::symlink, path::Path};
+
+    use super::{check_package, LinkTargets, QaCheck, QaLevel};
+
+    /// Create the symlinks of a package directory from a list of (path, target)
+    fn link(pkg_dir: &Path, links: &[(&str, &str)]) {
+        for (path, target) in links {
+            let path = pkg_dir.join(path.trim_start_matches('/'));
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            symlink(target, path).unwrap();
+        }
+    }
+
+    #[test]
+    fn dangling_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let (pkg_dir, other_dir, root) = (dir.path().join("pkg"), dir.path().join("other"), dir.path().join("root"));
+
+        fs::create_dir_all(pkg_dir.join("usr/lib")).unwrap();
+        fs::write(pkg_dir.join("usr/lib/libfoo.so.1"), "").unwrap();
+        fs::create_dir_all(other_dir.join("usr/share/other")).unwrap();
+        fs::write(other_dir.join("usr/share/other/data"), "").unwrap();
+
+        // Installed symlinks are followed, the files they lead to must be installed
+        fs::create_dir_all(root.join("usr/lib")).unwrap();
+        symlink("usr/lib", root.join("lib")).unwrap();
+
+        link(&pkg_dir, &[
+            ("/usr/lib/libfoo.so", "libfoo.so.1"),
+            ("/usr/lib/libfoo-abs.so", "/usr/lib/libfoo.so.1"),
+            ("/usr/share/foo/data", "../other/data"),
+            ("/usr/bin/installed", "/lib/libinstalled.so.2"),
+            ("/usr/bin/loop", "loop"),
+            ("/usr/bin/loop-a", "loop-b"),
+            ("/usr/bin/loop-b", "loop-a"),
+            ("/usr/bin/missing", "/usr/lib/libmissing.so"),
+            ("/usr/bin/sh", "/bin/sh"),
+            ("/usr/bin/host", "/etc/passwd"),
+            ("/usr/lib/libfoo-up.so", "../../../../../../usr/lib/libfoo.so.1"),
+        ]);
+
+        let link_targets = LinkTargets {
+            pkg_dirs: vec![pkg_dir.as_path(), other_dir.as_path()],
+            root: &root,
+            installed: HashSet::from(["/usr", "/usr/lib", "/usr/lib/libinstalled.so.2", "/bin/sh"]),
+        };
+
+        let levels = QaCheck::ALL.into_iter()
+            .map(|check| (check, if check == QaCheck::DanglingSymlink { QaLevel::Error } else { QaLevel::Ignore }))
+            .collect::<BTreeMap<_, _>>();
+
+        let mut errors = check_package("foo", &pkg_dir, dir.path(), &levels, &link_targets).unwrap();
+        errors.sort();
+
+        // Files of the build host count for nothing
+        assert_eq!(errors, [
+            "foo: /usr/bin/host points to missing /etc/passwd [dangling-symlink]",
+            "foo: /usr/bin/loop is a symlink loop [dangling-symlink]",
+            "foo: /usr/bin/loop-a is a symlink loop [dangling-symlink]",
+            "foo: /usr/bin/loop-b is a symlink loop [dangling-symlink]",
+            "foo: /usr/bin/missing points to missing /usr/lib/libmissing.so [dangling-symlink]",
+        ]);
+    }
+}
@@ -0,0 +1,129 @@
+use std::path::{Component, Path, PathBuf};
+use serde::Serialize;
+
+use crate::database::{Database, InstalledPackage};
+use crate::error::{Error, Result};
+
+/// Name and version of an installed package, as listed by `query list` and `query owns`
+#[derive(Serialize)]
+struct PackageSummary<'a> {
+    name: &'a str,
+    version: String,
+}
+
+impl<'a> From<&'a InstalledPackage> for PackageSummary<'a> {
+    fn from(package: &'a InstalledPackage) -> Self {
+        PackageSummary { name: &package.info.name, version: package.info.version.to_string() }
+    }
+}
+
+/// Print every installed package with its version
+pub fn list(database: &Database, json: bool) -> Result<()> {
+    let packages = database.packages()?;
+
+    if json {
+        let summaries: Vec<PackageSummary> = packages.iter().map(PackageSummary::from).collect();
+        println!("{}", serde_json::to_string_pretty(&summaries)?);
+        return Ok(());
+    }
+
+    for package in &packages {
+        println!("{} {}", package.info.name, package.info.version);
+    }
+
+    Ok(())
+}
+
+/// Print the metadata of an installed package
+pub fn info(database: &Database, name: &str, json: bool) -> Result<()> {
+    let info = get_installed(database, name)?.info;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&info)?);
+        return Ok(());
+    }
+
+    let list = |values: Vec<String>| if values.is_empty() { "None".to_string() } else { values.join("  ") };
+
+    println!("Name            : {}", info.name);
+    println!("Version         : {}", info.version);
+    println!("Description     : {}", info.description);
+    println!("URL             : {}", info.url);
+    println!("License         : {}", info.license);
+    println!("Architecture    : {}", list(info.arch.clone()));
+    println!("Provides        : {}", list(info.provides.iter().map(ToString::to_string).collect()));
+    println!("Depends On      : {}", list(info.dependencies.iter().map(ToString::to_string).collect()));
+    println!("Conflicts With  : {}", list(info.conflicts.iter().map(ToString::to_string).collect()));
+    println!("Replaces        : {}", list(info.replaces.iter().map(ToString::to_string).collect()));
+    println!("Backup Files    : {}", list(info.backup.iter().map(|file| file.path.clone()).collect()));
+    println!("Maintainers     : {}", list(info.maintainers.clone()));
+    println!("Dev Build       : {}", if info.dev { "Yes" } else { "No" });
+
+    Ok(())
+}
+
+/// Print the files, directories and symlinks of an installed package
+pub fn files(database: &Database, name: &str, json: bool) -> Result<()> {
+    let manifest = get_installed(database, name)?.manifest;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&manifest.entries)?);
+        return Ok(());
+    }
+
+    for entry in &manifest.entries {
+        match &entry.link {
+            Some(link) => println!("{} -> {}", entry.path, link),
+            None => println!("{}", entry.path),
+        }
+    }
+
+    Ok(())
+}
+
+/// Print the packages owning a path, several packages can own the same directory
+pub fn owns(database: &Database, path: &Path, json: bool) -> Result<()> {
+    let path = normalize(path);
+    let packages = database.packages()?;
+
+    let owners: Vec<&InstalledPackage> = packages.iter()
+        .filter(|package| package.manifest.entries.iter().any(|entry| entry.path == path))
+        .collect();
+
+    if owners.is_empty() {
+        return Err(Error::NotOwned(path));
+    }
+
+    if json {
+        let summaries: Vec<PackageSummary> = owners.into_iter().map(PackageSummary::from).collect();
+        println!("{}", serde_json::to_string_pretty(&summaries)?);
+        return Ok(());
+    }
+
+    for owner in owners {
+        println!("{} is owned by {}", path, owner.info.display_name());
+    }
+
+    Ok(())
+}
+
+fn get_installed(database: &Database, name: &str) -> Result<InstalledPackage> {
+    database.get(name)?.ok_or_else(|| Error::NotInstalled(name.to_string()))
+}
+
+/// Turn a path into the form used in manifests: absolute, without "." or trailing slashes
+fn normalize(path: &Path) -> String {
+    let mut normalized = PathBuf::from("/");
+
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => normalized.push(name),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => {}
+        }
+    }
+
+    normalized.to_string_lossy().into_owned()
+}
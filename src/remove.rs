@@ -0,0 +1,77 @@
+use std::path::Path;
+
+use crate::database::{Database, InstalledPackage};
+use crate::error::{Error, Result};
+use crate::install::remove_files;
+use crate::transaction::Transaction;
+
+/// Remove installed packages from root, deleting their files and pruning emptied directories
+///
+/// Packages still needed by another installed package are only removed when forced. Every
+/// package is removed in a single transaction.
+pub fn remove_packages(root: &Path, names: &[String], force: bool) -> Result<()> {
+    let installed = Database::open(root).packages()?;
+
+    let mut removed = Vec::new();
+
+    for name in names {
+        match installed.iter().find(|package| &package.info.name == name) {
+            Some(package) => removed.push(package),
+            None => return Err(Error::NotInstalled(name.clone())),
+        }
+    }
+
+    let remaining: Vec<&InstalledPackage> = installed.iter()
+        .filter(|package| !names.contains(&package.info.name))
+        .collect();
+
+    let broken = broken_dependencies(&removed, &remaining);
+
+    if !broken.is_empty() {
+        if !force {
+            return Err(Error::RequiredBy(broken));
+        }
+
+        for dependency in &broken {
+            eprintln!("Warning: {}", dependency);
+        }
+    }
+
+    Transaction::run(root, |transaction| {
+        for package in &removed {
+            println!("Removing {}", package.info.display_name());
+
+            let version = [package.info.version.to_string()];
+            transaction.run_hook(&package.info.name, package.install.as_deref(), "PRE_REMOVE", &version)?;
+            transaction.remove_package(&package.info.name)?;
+            transaction.queue_hook(&package.info.name, package.install.as_deref(), "POST_REMOVE", &version);
+        }
+
+        // Directories are only pruned once no remaining package lists them
+        for package in &removed {
+            remove_files(transaction, package)?;
+            transaction.record(format!("removed {} ({})", package.info.name, package.info.version))?;
+        }
+
+        Ok(())
+    })
+}
+
+/// Describe every dependency of the remaining packages that only a removed package satisfies
+fn broken_dependencies(removed: &[&InstalledPackage], remaining: &[&InstalledPackage]) -> Vec<String> {
+    let mut broken = Vec::new();
+
+    for package in remaining {
+        for dependency in &package.info.dependencies {
+            let Some(provider) = removed.iter().find(|removed| dependency.is_satisfied_by(&removed.info)) else {
+                continue;
+            };
+
+            if !remaining.iter().any(|other| dependency.is_satisfied_by(&other.info)) {
+                broken.push(format!("{} depends on {} (provided by {})", package.info.display_name(), dependency, provider.info.name));
+            }
+        }
+    }
+
+    broken
+}
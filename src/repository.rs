@@ -0,0 +1,195 @@
+use std::{fs, path::{Path, PathBuf}};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::file_operations::sha256sum_file;
+use crate::package_info::{FinalPackageInfo, PackageArchive};
+
+/// Name of the index file at the top of a repository
+pub const INDEX_FILE: &str = "index.json";
+
+/// Format version written in new indexes
+pub const INDEX_VERSION: u32 = 1;
+
+/// Extensions of the package archives picked up by `repo rebuild`
+const ARCHIVE_EXTENSIONS: [&str; 4] = [".tar.zst", ".tar.xz", ".tar.gz", ".tar"];
+
+/// A package archive of the repository
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IndexEntry {
+    /// File name of the archive, relative to the repository
+    pub filename: String,
+    pub size: u64,
+    pub sha256: String,
+    pub info: FinalPackageInfo,
+}
+
+impl IndexEntry {
+    /// Describe a package archive, it is stored at the top of the repository under its file name
+    pub fn from_archive(path: &Path) -> Result<IndexEntry> {
+        let package = PackageArchive::open(path)?;
+
+        let filename = path.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .ok_or_else(|| Error::Repository(format!("{:?} is not a file", path)))?;
+
+        Ok(IndexEntry {
+            filename,
+            size: fs::metadata(path)?.len(),
+            sha256: sha256sum_file(path)?,
+            info: package.info,
+        })
+    }
+}
+
+/// Index of a repository, listing the newest version of every package it holds
+///
+/// Stored as compact JSON in index.json at the top of the repository so clients can resolve
+/// packages without opening the archives.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RepositoryIndex {
+    pub version: u32,
+    /// Sorted by package name
+    pub packages: Vec<IndexEntry>,
+}
+
+impl Default for RepositoryIndex {
+    fn default() -> Self {
+        RepositoryIndex { version: INDEX_VERSION, packages: Vec::new() }
+    }
+}
+
+impl RepositoryIndex {
+    /// Parse an index, rejecting format versions this vrdpkg does not know
+    pub fn parse(content: &str) -> Result<RepositoryIndex> {
+        let index: RepositoryIndex = serde_json::from_str(content)?;
+
+        if index.version != INDEX_VERSION {
+            return Err(Error::Repository(format!("unsupported index version {}", index.version)));
+        }
+
+        Ok(index)
+    }
+
+    /// Read the index of a repository directory, a missing index is empty
+    pub fn load(repo_dir: &Path) -> Result<RepositoryIndex> {
+        let path = repo_dir.join(INDEX_FILE);
+
+        if !path.exists() {
+            return Ok(RepositoryIndex::default());
+        }
+
+        RepositoryIndex::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Write the index of a repository directory, replacing the previous one atomically
+    pub fn save(&self, repo_dir: &Path) -> Result<()> {
+        let path = repo_dir.join(INDEX_FILE);
+        let temporary_path = repo_dir.join(format!("{}.tmp", INDEX_FILE));
+
+        fs::write(&temporary_path, serde_json::to_string(self)?)?;
+        fs::rename(temporary_path, path)?;
+
+        Ok(())
+    }
+
+    /// Add a package, keeping only the newest version of every package name
+    ///
+    /// Returns false if the index already holds the same or a newer version.
+    pub fn insert(&mut self, entry: IndexEntry) -> bool {
+        match self.packages.binary_search_by(|existing| existing.info.name.cmp(&entry.info.name)) {
+            Ok(position) if self.packages[position].info.version >= entry.info.version => false,
+            Ok(position) => {
+                self.packages[position] = entry;
+                true
+            }
+            Err(position) => {
+                self.packages.insert(position, entry);
+                true
+            }
+        }
+    }
+
+    /// Drop a package by name, returning the removed entry
+    pub fn remove(&mut self, name: &str) -> Option<IndexEntry> {
+        let position = self.packages.iter().position(|entry| entry.info.name == name)?;
+
+        Some(self.packages.remove(position))
+    }
+}
+
+/// Add package archives to a repository, copying them into it if needed
+pub fn repo_add(repo_dir: &Path, archives: &[PathBuf]) -> Result<()> {
+    fs::create_dir_all(repo_dir)?;
+
+    let mut index = RepositoryIndex::load(repo_dir)?;
+
+    for archive in archives {
+        let entry = IndexEntry::from_archive(archive)?;
+        let name = entry.info.display_name();
+        let destination = repo_dir.join(&entry.filename);
+
+        if !index.insert(entry) {
+            println!("Skipped {}, the repository already has this or a newer version", name);
+            continue;
+        }
+
+        if !destination.exists() || !same_file(archive, &destination)? {
+            fs::copy(archive, &destination)?;
+        }
+
+        println!("Added {}", name);
+    }
+
+    index.save(repo_dir)
+}
+
+/// Drop packages from the index of a repository, their archives are left in place
+pub fn repo_remove(repo_dir: &Path, names: &[String]) -> Result<()> {
+    let mut index = RepositoryIndex::load(repo_dir)?;
+
+    for name in names {
+        match index.remove(name) {
+            Some(entry) => println!("Removed {}", entry.info.display_name()),
+            None => return Err(Error::Repository(format!("{} is not in the repository", name))),
+        }
+    }
+
+    index.save(repo_dir)
+}
+
+/// Regenerate the index from every package archive in the repository directory
+pub fn repo_rebuild(repo_dir: &Path) -> Result<()> {
+    let mut index = RepositoryIndex::default();
+
+    let mut paths = fs::read_dir(repo_dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<PathBuf>>>()?;
+    paths.sort();
+
+    for path in paths {
+        let is_archive = path.is_file() && path.file_name()
+            .map(|name| name.to_string_lossy())
+            .is_some_and(|name| ARCHIVE_EXTENSIONS.iter().any(|extension| name.ends_with(extension)));
+
+        if !is_archive {
+            continue;
+        }
+
+        match IndexEntry::from_archive(&path) {
+            Ok(entry) => {
+                index.insert(entry);
+            }
+            Err(e) => eprintln!("Warning: skipping {:?}: {}", path, e),
+        }
+    }
+
+    println!("Indexed {} package(s)", index.packages.len());
+
+    index.save(repo_dir)
+}
+
+/// Returns true if both paths point to the same file
+fn same_file(a: &Path, b: &Path) -> Result<bool> {
+    Ok(fs::canonicalize(a)? == fs::canonicalize(b)?)
+}
@@ -0,0 +1,476 @@
+use std::cmp::Reverse;
+
+use crate::dependency::Dependency;
+use crate::error::{Error, Result};
+use crate::package_info::FinalPackageInfo;
+
+/// Upper bound on the search steps, past it resolution gives up instead of running for ever
+const MAX_STEPS: usize = 100_000;
+
+#[derive(Clone, Copy)]
+enum Goal<'a> {
+    /// A dependency to satisfy, along with the candidate needing it (None for requested packages)
+    Satisfy { dependency: &'a Dependency, required_by: Option<usize> },
+    /// Every dependency of the candidate has been placed, it can be appended to the plan
+    Place(usize),
+}
+
+#[derive(Clone, Default)]
+struct State {
+    /// Candidates chosen so far, in the order they were chosen
+    selected: Vec<usize>,
+    /// Candidates in install order, dependencies first
+    plan: Vec<usize>,
+}
+
+/// A dependency several candidates can satisfy, tried in order until one leads to a solution
+struct Choice<'a> {
+    /// State and remaining goals from before the choice, restored when backtracking into it
+    state: State,
+    goals: Vec<Goal<'a>>,
+    candidates: Vec<usize>,
+    /// Index of the next candidate to try
+    next: usize,
+    needed_by: String,
+    /// Error of the first candidate that failed, reported if every candidate fails
+    first_error: Option<String>,
+}
+
+/// Where following the goals without making a choice leads
+enum Step<'a> {
+    Solved(State),
+    Failed(String),
+    Choose(Choice<'a>),
+}
+
+/// Chooses which of the available packages to install to satisfy a set of requests
+///
+/// Dependencies are satisfied by installed packages when possible, otherwise by a candidate
+/// providing them: a package with the same name is preferred, then the highest version. When a
+/// choice leads to a conflict the next provider is tried.
+pub struct Resolver<'a> {
+    available: Vec<&'a FinalPackageInfo>,
+    installed: Vec<&'a FinalPackageInfo>,
+    steps: usize,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(available: impl IntoIterator<Item = &'a FinalPackageInfo>, installed: impl IntoIterator<Item = &'a FinalPackageInfo>) -> Resolver<'a> {
+        Resolver {
+            available: available.into_iter().collect(),
+            installed: installed.into_iter().collect(),
+            steps: 0,
+        }
+    }
+
+    /// Resolve the requests into indices of available packages, in install order
+    ///
+    /// Requested packages are always taken from the available packages, even when an installed
+    /// package satisfies them.
+    pub fn resolve(&mut self, requests: &'a [Dependency]) -> Result<Vec<usize>> {
+        let goals = requests.iter().rev()
+            .map(|dependency| Goal::Satisfy { dependency, required_by: None })
+            .collect();
+
+        self.steps = 0;
+
+        self.solve(State::default(), goals)
+            .map(|state| state.plan)
+            .map_err(Error::Unresolvable)
+    }
+
+    /// Depth-first search over the candidates of each dependency, backtracking with an explicit
+    /// stack of choices so the depth of the search does not grow the call stack
+    fn solve(&mut self, state: State, goals: Vec<Goal<'a>>) -> std::result::Result<State, String> {
+        let mut choices: Vec<Choice<'a>> = Vec::new();
+        let mut step = self.advance(state, goals)?;
+
+        loop {
+            match step {
+                Step::Solved(state) => return Ok(state),
+                Step::Choose(choice) => choices.push(choice),
+                Step::Failed(error) => match choices.last_mut() {
+                    Some(choice) => {
+                        choice.first_error.get_or_insert(error);
+                    }
+                    None => return Err(error),
+                },
+            }
+
+            // Try the next candidate of the innermost choice, leaving the choices that ran out
+            step = loop {
+                let Some(choice) = choices.last_mut() else {
+                    unreachable!("a failed step without choices returns");
+                };
+
+                if let Some(step) = self.try_next_candidate(choice)? {
+                    break step;
+                }
+
+                let error = choices.pop().and_then(|choice| choice.first_error).unwrap_or_default();
+
+                match choices.last_mut() {
+                    Some(parent) => {
+                        parent.first_error.get_or_insert(error);
+                    }
+                    None => return Err(error),
+                }
+            };
+        }
+    }
+
+    /// Work through the goals until they are all met, one fails or a dependency needs a choice
+    /// between several candidates
+    fn advance(&mut self, mut state: State, mut goals: Vec<Goal<'a>>) -> std::result::Result<Step<'a>, String> {
+        loop {
+            self.steps += 1;
+
+            if self.steps > MAX_STEPS {
+                return Err(format!("gave up after {} steps", MAX_STEPS));
+            }
+
+            let Some(goal) = goals.pop() else {
+                return Ok(match self.check(&state) {
+                    Ok(()) => Step::Solved(state),
+                    Err(e) => Step::Failed(e),
+                });
+            };
+
+            let (dependency, required_by) = match goal {
+                Goal::Place(candidate) => {
+                    state.plan.push(candidate);
+                    continue;
+                }
+                Goal::Satisfy { dependency, required_by } => (dependency, required_by),
+            };
+
+            let satisfied = state.selected.iter().any(|&selected| dependency.is_satisfied_by(self.available[selected]))
+                || (required_by.is_some() && self.kept_installed(&state).any(|installed| dependency.is_satisfied_by(installed)));
+
+            if satisfied {
+                continue;
+            }
+
+            let needed_by = match required_by {
+                Some(candidate) => format!(" (needed by {})", self.available[candidate].display_name()),
+                None => String::new(),
+            };
+
+            let candidates = self.candidates(dependency);
+
+            if candidates.is_empty() {
+                return Ok(Step::Failed(format!("nothing provides {}{}", dependency, needed_by)));
+            }
+
+            return Ok(Step::Choose(Choice { state, goals, candidates, next: 0, needed_by, first_error: None }));
+        }
+    }
+
+    /// Select the next candidate of a choice that does not conflict with the selection, returning
+    /// None once every candidate has been tried
+    fn try_next_candidate(&mut self, choice: &mut Choice<'a>) -> std::result::Result<Option<Step<'a>>, String> {
+        while let Some(&candidate) = choice.candidates.get(choice.next) {
+            choice.next += 1;
+
+            if let Some(conflict) = self.conflict(&choice.state, candidate) {
+                choice.first_error.get_or_insert_with(|| format!("cannot install {}{}: {}", self.available[candidate].display_name(), choice.needed_by, conflict));
+                continue;
+            }
+
+            let mut state = choice.state.clone();
+            state.selected.push(candidate);
+
+            let mut goals = choice.goals.clone();
+            goals.push(Goal::Place(candidate));
+            goals.extend(self.available[candidate].dependencies.iter().rev()
+                .map(|dependency| Goal::Satisfy { dependency, required_by: Some(candidate) }));
+
+            return self.advance(state, goals).map(Some);
+        }
+
+        Ok(None)
+    }
+
+    /// Available packages satisfying a dependency, most preferred first
+    fn candidates(&self, dependency: &Dependency) -> Vec<usize> {
+        let mut candidates: Vec<usize> = (0..self.available.len())
+            .filter(|&candidate| dependency.is_satisfied_by(self.available[candidate]))
+            .collect();
+
+        candidates.sort_by_key(|&candidate| {
+            let info = self.available[candidate];
+            (info.name != dependency.name, Reverse(&info.version), &info.name)
+        });
+
+        candidates
+    }
+
+    /// Returns true if a selected package upgrades or replaces the installed package
+    fn is_superseded(&self, state: &State, installed: &FinalPackageInfo) -> bool {
+        state.selected.iter().any(|&selected| supersedes(self.available[selected], installed))
+    }
+
+    /// Installed packages that stay installed with the current selection
+    fn kept_installed<'s>(&'s self, state: &'s State) -> impl Iterator<Item = &'a FinalPackageInfo> + 's {
+        self.installed.iter().copied().filter(move |installed| !self.is_superseded(state, installed))
+    }
+
+    /// Describe why a candidate cannot be added to the selection, if it cannot
+    fn conflict(&self, state: &State, candidate: usize) -> Option<String> {
+        let info = self.available[candidate];
+
+        for &selected in &state.selected {
+            let other = self.available[selected];
+
+            if other.name == info.name {
+                return Some(format!("{} is already selected", other.display_name()));
+            }
+
+            if conflicts(info, other) {
+                return Some(format!("conflicts with {}", other.display_name()));
+            }
+        }
+
+        self.kept_installed(state)
+            .filter(|installed| !supersedes(info, installed))
+            .find(|installed| conflicts(info, installed))
+            .map(|installed| format!("conflicts with installed package {}", installed.display_name()))
+    }
+
+    /// Check that the complete selection leaves every dependency satisfied
+    fn check(&self, state: &State) -> std::result::Result<(), String> {
+        let kept: Vec<&FinalPackageInfo> = self.kept_installed(state).collect();
+        let satisfied = |dependency: &Dependency| {
+            state.selected.iter().any(|&selected| dependency.is_satisfied_by(self.available[selected]))
+                || kept.iter().any(|installed| dependency.is_satisfied_by(installed))
+        };
+
+        for &selected in &state.selected {
+            let info = self.available[selected];
+
+            if let Some(dependency) = info.dependencies.iter().find(|dependency| !satisfied(dependency)) {
+                return Err(format!("{} needs {} which would be replaced", info.display_name(), dependency));
+            }
+        }
+
+        for installed in &kept {
+            // Only report dependencies the change breaks, not the ones that were already missing
+            let broken = installed.dependencies.iter().find(|dependency| {
+                !satisfied(dependency) && self.installed.iter().any(|before| dependency.is_satisfied_by(before))
+            });
+
+            if let Some(dependency) = broken {
+                return Err(format!("installed package {} needs {}", installed.display_name(), dependency));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns true if installing the package removes the installed one, by upgrading or replacing it
+pub fn supersedes(info: &FinalPackageInfo, installed: &FinalPackageInfo) -> bool {
+    info.name == installed.name || info.replaces.iter().any(|dependency| dependency.is_satisfied_by(installed))
+}
+
+/// Returns true if either package declares a conflict with the other
+fn conflicts(a: &FinalPackageInfo, b: &FinalPackageInfo) -> bool {
+    a.conflicts.iter().any(|dependency| dependency.is_satisfied_by(b))
+        || b.conflicts.iter().any(|dependency| dependency.is_satisfied_by(a))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Resolver;
+    use crate::dependency::Dependency;
+    use crate::error::Error;
+    use crate::package_info::FinalPackageInfo;
+
+    /// A package whose fields are written as "name version", then lists of dependencies,
+    /// provides, conflicts and replaces
+    fn package(name: &str, dependencies: &[&str], provides: &[&str], conflicts: &[&str], replaces: &[&str]) -> FinalPackageInfo {
+        let (name, version) = name.split_once(' ').unwrap();
+        let parse = |list: &[&str]| list.iter().map(|item| item.parse().unwrap()).collect();
+
+        FinalPackageInfo {
+            dependencies: parse(dependencies),
+            conflicts: parse(conflicts),
+            provides: provides.iter().map(|provide| provide.parse().unwrap()).collect(),
+            replaces: parse(replaces),
+            ..FinalPackageInfo::bare(name, version)
+        }
+    }
+
+    fn simple(name: &str, dependencies: &[&str]) -> FinalPackageInfo {
+        package(name, dependencies, &[], &[], &[])
+    }
+
+    /// Resolve the requests, returning the plan as "name version" or the explanation
+    fn resolve(available: &[FinalPackageInfo], installed: &[FinalPackageInfo], requests: &[&str]) -> Result<Vec<String>, String> {
+        let requests: Vec<Dependency> = requests.iter().map(|request| request.parse().unwrap()).collect();
+
+        match Resolver::new(available, installed).resolve(&requests) {
+            Ok(plan) => Ok(plan.into_iter().map(|index| available[index].display_name()).collect()),
+            Err(Error::Unresolvable(message)) => Err(message),
+            Err(e) => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn installs_dependencies_first() {
+        let available = [
+            simple("app 1.0.0", &["libfoo", "libbar"]),
+            simple("libfoo 1.0.0", &["libbar"]),
+            simple("libbar 1.0.0", &[]),
+        ];
+
+        assert_eq!(resolve(&available, &[], &["app"]), Ok(vec!["libbar 1.0.0".into(), "libfoo 1.0.0".into(), "app 1.0.0".into()]));
+    }
+
+    #[test]
+    fn provider_choice() {
+        let available = [
+            package("zsh 5.9.0", &[], &["sh"], &[], &[]),
+            package("bash 5.2.0", &[], &["sh"], &[], &[]),
+            package("dash 0.5.12", &[], &["sh"], &[], &[]),
+            package("sh 1.0.0", &[], &[], &[], &[]),
+            simple("libfoo 1.0.0", &[]),
+            simple("libfoo 1.2.0", &[]),
+            simple("libfoo 1.1.0", &[]),
+        ];
+
+        // A package named after the dependency wins over the providers
+        assert_eq!(resolve(&available, &[], &["sh"]), Ok(vec!["sh 1.0.0".into()]));
+        // Then the highest version
+        assert_eq!(resolve(&available, &[], &["libfoo"]), Ok(vec!["libfoo 1.2.0".into()]));
+
+        let providers = &available[..3];
+        assert_eq!(resolve(providers, &[], &["sh"]), Ok(vec!["zsh 5.9.0".into()]));
+    }
+
+    #[test]
+    fn installed_packages_satisfy_dependencies() {
+        let available = [simple("app 1.0.0", &["libfoo"]), simple("libfoo 2.0.0", &[])];
+        let installed = [simple("libfoo 1.0.0", &[])];
+
+        assert_eq!(resolve(&available, &installed, &["app"]), Ok(vec!["app 1.0.0".into()]));
+        // Requests always come from the available packages
+        assert_eq!(resolve(&available, &installed, &["libfoo"]), Ok(vec!["libfoo 2.0.0".into()]));
+    }
+
+    #[test]
+    fn version_constraints() {
+        let available = [
+            simple("app 1.0.0", &["libfoo>=1.1.0", "libfoo<2"]),
+            simple("libfoo 2.0.0", &[]),
+            simple("libfoo 1.1.0", &[]),
+            simple("libfoo 1.0.0", &[]),
+        ];
+
+        // libfoo 2.0.0 is preferred for libfoo>=1.1.0 but libfoo<2 then rejects it
+        assert_eq!(resolve(&available, &[], &["app"]), Ok(vec!["libfoo 1.1.0".into(), "app 1.0.0".into()]));
+        assert_eq!(resolve(&available, &[], &["libfoo=1.0.0"]), Ok(vec!["libfoo 1.0.0".into()]));
+        assert_eq!(resolve(&available, &[], &["libfoo>2"]), Err("nothing provides libfoo>2.0.0".into()));
+    }
+
+    #[test]
+    fn conflicts() {
+        let available = [
+            package("bash 5.2.0", &[], &["sh"], &["busybox"], &[]),
+            package("dash 0.5.12", &[], &["sh"], &[], &[]),
+            simple("app 1.0.0", &["sh"]),
+            simple("busybox 1.36.0", &[]),
+        ];
+
+        // bash is the preferred sh, its conflict with busybox makes the resolver backtrack to dash
+        assert_eq!(
+            resolve(&available, &[], &["app", "busybox"]),
+            Ok(vec!["dash 0.5.12".into(), "app 1.0.0".into(), "busybox 1.36.0".into()]),
+        );
+
+        assert_eq!(
+            resolve(&available, &[], &["busybox", "bash"]),
+            Err("cannot install bash 5.2.0: conflicts with busybox 1.36.0".into()),
+        );
+
+        let installed = [simple("busybox 1.36.0", &[])];
+
+        assert_eq!(
+            resolve(&available, &installed, &["bash"]),
+            Err("cannot install bash 5.2.0: conflicts with installed package busybox 1.36.0".into()),
+        );
+    }
+
+    #[test]
+    fn replaces() {
+        let available = [
+            package("openssl 3.0.0", &[], &[], &["libressl"], &["libressl"]),
+            simple("curl 8.0.0", &["openssl"]),
+        ];
+        let installed = [simple("libressl 3.8.0", &[])];
+
+        // Replacing an installed package overrides the conflict with it
+        assert_eq!(resolve(&available, &installed, &["curl"]), Ok(vec!["openssl 3.0.0".into(), "curl 8.0.0".into()]));
+
+        let installed = [simple("libressl 3.8.0", &[]), simple("nc 1.0.0", &["libressl"])];
+
+        assert_eq!(
+            resolve(&available, &installed, &["openssl"]),
+            Err("installed package nc 1.0.0 needs libressl".into()),
+        );
+    }
+
+    #[test]
+    fn backtracking() {
+        let available = [
+            simple("app 1.0.0", &["libfoo", "libbar"]),
+            simple("libfoo 2.0.0", &["libbaz>=2"]),
+            simple("libfoo 1.0.0", &["libbaz<2"]),
+            simple("libbar 1.0.0", &["libbaz<2"]),
+            simple("libbaz 2.0.0", &[]),
+            simple("libbaz 1.0.0", &[]),
+        ];
+
+        // libfoo 2.0.0 selects libbaz 2.0.0, which libbar cannot use, so libfoo 1.0.0 is chosen
+        assert_eq!(
+            resolve(&available, &[], &["app"]),
+            Ok(vec!["libbaz 1.0.0".into(), "libfoo 1.0.0".into(), "libbar 1.0.0".into(), "app 1.0.0".into()]),
+        );
+    }
+
+    #[test]
+    fn unsatisfiable_explanation() {
+        let available = [
+            simple("app 1.0.0", &["libfoo>=2"]),
+            simple("libfoo 1.0.0", &[]),
+            simple("tool 1.0.0", &["libmissing"]),
+        ];
+
+        assert_eq!(resolve(&available, &[], &["app"]), Err("nothing provides libfoo>=2.0.0 (needed by app 1.0.0)".into()));
+        assert_eq!(resolve(&available, &[], &["tool"]), Err("nothing provides libmissing (needed by tool 1.0.0)".into()));
+        assert_eq!(resolve(&available, &[], &["nothing"]), Err("nothing provides nothing".into()));
+
+        // Every candidate fails, the error of the first one is reported
+        let available = [
+            simple("app 1.0.0", &["libfoo"]),
+            simple("libfoo 2.0.0", &["libbar>=2"]),
+            simple("libfoo 1.0.0", &["libbar>=3"]),
+            simple("libbar 1.0.0", &[]),
+        ];
+
+        assert_eq!(resolve(&available, &[], &["app"]), Err("nothing provides libbar>=2.0.0 (needed by libfoo 2.0.0)".into()));
+    }
+
+    #[test]
+    fn long_dependency_chain() {
+        let available: Vec<FinalPackageInfo> = (0..2000)
+            .map(|i| simple(&format!("p{} 1.0.0", i), &[format!("p{}", i + 1).as_str()][..usize::from(i < 1999)]))
+            .collect();
+
+        let plan = resolve(&available, &[], &["p0"]).unwrap();
+
+        assert_eq!(plan.len(), 2000);
+        assert_eq!(plan.first().map(String::as_str), Some("p1999 1.0.0"));
+        assert_eq!(plan.last().map(String::as_str), Some("p0 1.0.0"));
+    }
+}
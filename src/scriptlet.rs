@@ -0,0 +1,152 @@
+use std::{fs, path::Path};
+use mlua::{Function, Lua, LuaOptions, StdLib, Value};
+
+use crate::archive::INSTALL_FILE;
+use crate::error::{Error, Result};
+use crate::lua_functions::register_scriptlet_functions;
+use crate::path_utils::sanitize_path;
+
+/// Functions a package can define to run while it is installed, upgraded or removed
+///
+/// They get the new version (PRE_INSTALL, POST_INSTALL), the new and old versions
+/// (POST_UPGRADE) or the old version (PRE_REMOVE, POST_REMOVE).
+pub const HOOKS: [&str; 5] = ["PRE_INSTALL", "POST_INSTALL", "POST_UPGRADE", "PRE_REMOVE", "POST_REMOVE"];
+
+/// Write the install scriptlets of a project into the package directory as .INSTALL
+///
+/// The scriptlets come from the Lua file named by the install field, hooks cannot be defined in
+/// buildpkg.lua since scriptlets run without the build script.
+pub fn embed_scriptlets(install: Option<&str>, working_dir: &Path, pkg_dir: &Path) -> Result<()> {
+    let install_path = pkg_dir.join(INSTALL_FILE);
+
+    match install {
+        Some(install) => {
+            let path = sanitize_path(working_dir, install)?;
+            let scriptlets = fs::read_to_string(&path)?;
+
+            check_scriptlets(&scriptlets, install)?;
+
+            println!("Embedding install scriptlets");
+            fs::write(install_path, scriptlets)?;
+        }
+        None if install_path.exists() => fs::remove_file(install_path)?,
+        None => {}
+    }
+
+    Ok(())
+}
+
+/// Create the Lua state scriptlets run in, without the io and os libraries or the base functions
+/// loading files, so scriptlets only reach the root through the scriptlet functions
+fn scriptlet_lua() -> mlua::Result<Lua> {
+    let lua = Lua::new_with(StdLib::COROUTINE | StdLib::TABLE | StdLib::STRING | StdLib::UTF8 | StdLib::MATH, LuaOptions::new())?;
+
+    for name in ["dofile", "loadfile"] {
+        lua.globals().raw_remove(name)?;
+    }
+
+    Ok(lua)
+}
+
+/// Check that the scriptlets load on their own and only define known hooks
+///
+/// The scriptlet functions are left out so the top level of the scriptlets cannot act on the
+/// build host.
+fn check_scriptlets(scriptlets: &str, name: &str) -> Result<()> {
+    let lua = scriptlet_lua()?;
+    lua.load(scriptlets).set_name(format!("@{}", name)).exec()?;
+
+    for pair in lua.globals().pairs::<Value, Value>() {
+        let (Value::String(global), Value::Function(_)) = pair? else {
+            continue;
+        };
+
+        let global = global.to_string_lossy();
+
+        if global.chars().all(|c| c.is_ascii_uppercase() || c == '_') && !HOOKS.contains(&global.as_str()) {
+            return Err(Error::InvalidPackage(format!("{}: unknown install hook {}", name, global)));
+        }
+    }
+
+    Ok(())
+}
+
+/// Run a hook of a package's install scriptlets inside root, doing nothing if it is not defined
+///
+/// Scriptlets have no io or os library, commands they execute run chrooted into root.
+pub fn run_hook(root: &Path, package: &str, scriptlets: &str, hook: &str, versions: &[String]) -> Result<()> {
+    let error = |source| Error::Scriptlet { package: package.to_string(), hook: hook.to_string(), source };
+
+    let lua = scriptlet_lua().map_err(error)?;
+    register_scriptlet_functions(&lua, root.to_path_buf()).map_err(error)?;
+    lua.load(scriptlets).set_name(format!("@{}/{}", package, INSTALL_FILE)).exec().map_err(error)?;
+
+    let Ok(function) = lua.globals().get::<Function>(hook) else {
+        return Ok(());
+    };
+
+    println!("Running {} of {}", hook, package);
+
+    function.call::<()>(mlua::Variadic::from_iter(versions.iter().cloned())).map_err(error)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{embed_scriptlets, run_hook};
+    use crate::archive::INSTALL_FILE;
+
+    #[test]
+    fn embed_install_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let pkg_dir = dir.path().join("pkg");
+        fs::create_dir(&pkg_dir).unwrap();
+
+        let scriptlets = "local function log(v) print(v) end\n\nfunction POST_INSTALL(new)\n  log(new)\nend\n";
+        fs::write(dir.path().join("hooks.lua"), scriptlets).unwrap();
+
+        embed_scriptlets(Some("hooks.lua"), dir.path(), &pkg_dir).unwrap();
+        assert_eq!(fs::read_to_string(pkg_dir.join(INSTALL_FILE)).unwrap(), scriptlets);
+
+        // A package without install file loses the scriptlets of a previous build
+        embed_scriptlets(None, dir.path(), &pkg_dir).unwrap();
+        assert!(!pkg_dir.join(INSTALL_FILE).exists());
+    }
+
+    #[test]
+    fn embed_rejects() {
+        let cases = [
+            "function POST_INSTAL() end",
+            "function POST_INSTALL( end",
+            "io.write('at load time')",
+            "dofile('/etc/passwd')",
+            "function PRE_REMOVE() end\nos.remove('/')",
+        ];
+
+        for scriptlets in cases {
+            let dir = tempfile::tempdir().unwrap();
+            fs::write(dir.path().join("hooks.lua"), scriptlets).unwrap();
+
+            assert!(embed_scriptlets(Some("hooks.lua"), dir.path(), dir.path()).is_err(), "{}", scriptlets);
+            assert!(!dir.path().join(INSTALL_FILE).exists(), "{}", scriptlets);
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        assert!(embed_scriptlets(Some("../hooks.lua"), dir.path(), dir.path()).is_err());
+    }
+
+    #[test]
+    fn run_hooks() {
+        let root = tempfile::tempdir().unwrap();
+        let scriptlets = "function POST_UPGRADE(new, old)\n  assert(new == '2.0.0' and old == '1.0.0')\nend\n\
+            function PRE_REMOVE()\n  io.open(ROOT .. '/f', 'w')\nend\n";
+
+        run_hook(root.path(), "p", scriptlets, "POST_UPGRADE", &["2.0.0".to_string(), "1.0.0".to_string()]).unwrap();
+        run_hook(root.path(), "p", scriptlets, "POST_INSTALL", &["2.0.0".to_string()]).unwrap();
+
+        assert!(run_hook(root.path(), "p", scriptlets, "POST_UPGRADE", &["3.0.0".to_string(), "1.0.0".to_string()]).is_err());
+        assert!(run_hook(root.path(), "p", scriptlets, "PRE_REMOVE", &["2.0.0".to_string()]).is_err());
+        assert!(!root.path().join("f").exists());
+    }
+}
@@ -0,0 +1,196 @@
+use std::{collections::{BTreeMap, BTreeSet, HashSet}, path::{Path, PathBuf}};
+
+use crate::database::{file_owners, Database};
+use crate::dependency::{Dependency, Provide};
+use crate::elf::{dynamic_section, elf_files, ElfKind};
+use crate::error::Result;
+use crate::package_info::PackageInfo;
+
+/// Directories searched by the dynamic linker by default, only the libraries installed in one of
+/// them are provided by soname
+pub const LIBRARY_DIRS: [&str; 4] = ["/usr/lib", "/lib", "/usr/lib64", "/lib64"];
+
+/// Prefix of the provides and dependencies naming a shared library, e.g. "so:libz.so.1"
+pub const SONAME_PREFIX: &str = "so:";
+
+/// Shared libraries provided and needed by the ELF files of a package directory
+struct Libraries {
+    /// Sonames of the libraries installed in one of the LIBRARY_DIRS
+    provided: BTreeSet<String>,
+    /// Sonames and file names of every shared object of the package, private ones included
+    shipped: HashSet<String>,
+    /// Libraries needed by the package, along with the files needing each
+    needed: BTreeMap<String, Vec<String>>,
+}
+
+/// Read the dynamic sections of every ELF file of a package directory
+fn scan_libraries(pkg_dir: &Path) -> Result<Libraries> {
+    let mut libraries = Libraries {
+        provided: BTreeSet::new(),
+        shipped: HashSet::new(),
+        needed: BTreeMap::new(),
+    };
+
+    for elf_file in elf_files(pkg_dir)? {
+        let section = dynamic_section(&elf_file.file)?;
+
+        if elf_file.kind == ElfKind::SharedObject && let Some((parent, file_name)) = elf_file.path.rsplit_once('/') {
+            libraries.shipped.insert(file_name.to_string());
+
+            if let Some(soname) = section.soname {
+                if LIBRARY_DIRS.contains(&parent) {
+                    libraries.provided.insert(soname.clone());
+                }
+
+                libraries.shipped.insert(soname);
+            }
+        }
+
+        for library in section.needed {
+            libraries.needed.entry(library).or_default().push(elf_file.path.clone());
+        }
+    }
+
+    Ok(libraries)
+}
+
+/// Add "so:<soname>" provides and dependencies to packages built together
+///
+/// Libraries a package needs but does not ship are looked up in the other packages of the build,
+/// then in the packages installed under root. Installed packages without a matching provide are
+/// depended on by name, and libraries no package provides are reported once each.
+pub fn add_library_relations(packages: &mut [(PackageInfo, PathBuf)], root: &Path) -> Result<()> {
+    let libraries = packages.iter()
+        .map(|(_, pkg_dir)| scan_libraries(pkg_dir))
+        .collect::<Result<Vec<Libraries>>>()?;
+
+    let installed = Database::open(root).packages()?;
+    let owners = file_owners(&installed);
+
+    // Files needing each library no package provides, as "path of package"
+    let mut unresolved: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+
+    for ((info, _), own) in packages.iter_mut().zip(&libraries) {
+        for soname in &own.provided {
+            let provide = Provide { name: format!("{}{}", SONAME_PREFIX, soname), version: None };
+
+            if !info.provides.iter().any(|other| other.name == provide.name) {
+                println!("{} provides {}", info.name, provide);
+                info.provides.push(provide);
+            }
+        }
+
+        for (library, paths) in &own.needed {
+            if own.shipped.contains(library) {
+                continue;
+            }
+
+            let provide_name = format!("{}{}", SONAME_PREFIX, library);
+
+            let provided = libraries.iter().any(|other| other.provided.contains(library))
+                || installed.iter().any(|package| package.info.provides.iter().any(|provide| provide.name == provide_name));
+
+            let owner = LIBRARY_DIRS.iter()
+                .find_map(|dir| owners.get(format!("{}/{}", dir, library).as_str()));
+
+            let name = match owner {
+                _ if provided => provide_name,
+                Some(owner) => owner.to_string(),
+                None => {
+                    unresolved.entry(library).or_default().extend(paths.iter().map(|path| format!("{} of {}", path, info.name)));
+                    continue;
+                }
+            };
+
+            if name != info.name && !info.dependencies.iter().any(|dependency| dependency.name == name) {
+                println!("{} depends on {} for {}", info.name, name, library);
+                info.dependencies.push(Dependency { name, constraint: None });
+            }
+        }
+    }
+
+    for (library, users) in unresolved {
+        match users.as_slice() {
+            [user] => eprintln!("Warning: no package provides {}, needed by {}", library, user),
+            [user, others @ ..] => eprintln!("Warning: no package provides {}, needed by {} and {} other files", library, user, others.len()),
+            [] => {}
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::{Path, PathBuf}};
+
+    use super::{add_library_relations, scan_libraries};
+    use crate::elf::{tests::elf_file, ElfKind};
+    use crate::package_info::PackageInfo;
+
+    /// Write an ELF file into a package directory
+    fn write_elf(pkg_dir: &Path, path: &str, kind: ElfKind, soname: Option<&str>, needed: &[&str]) {
+        let file = pkg_dir.join(path.trim_start_matches('/'));
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(file, elf_file(kind, true, false, soname, needed)).unwrap();
+    }
+
+    fn package(name: &str, dir: &Path) -> (PackageInfo, PathBuf) {
+        let pkg_dir = dir.join(name);
+        fs::create_dir_all(&pkg_dir).unwrap();
+
+        (PackageInfo { name: name.to_string(), ..Default::default() }, pkg_dir)
+    }
+
+    #[test]
+    fn scan() {
+        let dir = tempfile::tempdir().unwrap();
+
+        write_elf(dir.path(), "/usr/lib/libfoo.so.1.2", ElfKind::SharedObject, Some("libfoo.so.1"), &["libc.so.6"]);
+        write_elf(dir.path(), "/usr/lib/foo/libplugin.so", ElfKind::SharedObject, Some("libplugin.so"), &["libfoo.so.1"]);
+        write_elf(dir.path(), "/usr/bin/foo", ElfKind::Executable, None, &["libfoo.so.1", "libc.so.6"]);
+        fs::write(dir.path().join("usr/bin/script"), "#!/bin/sh\n").unwrap();
+
+        let libraries = scan_libraries(dir.path()).unwrap();
+
+        assert_eq!(libraries.provided.into_iter().collect::<Vec<_>>(), ["libfoo.so.1"]);
+
+        let mut shipped: Vec<_> = libraries.shipped.into_iter().collect();
+        shipped.sort();
+        assert_eq!(shipped, ["libfoo.so.1", "libfoo.so.1.2", "libplugin.so"]);
+
+        let needed: Vec<_> = libraries.needed.into_iter().collect();
+        assert_eq!(needed, [
+            ("libc.so.6".to_string(), vec!["/usr/bin/foo".to_string(), "/usr/lib/libfoo.so.1.2".to_string()]),
+            ("libfoo.so.1".to_string(), vec!["/usr/bin/foo".to_string(), "/usr/lib/foo/libplugin.so".to_string()]),
+        ]);
+    }
+
+    #[test]
+    fn relations() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+
+        let mut packages = vec![package("libfoo", dir.path()), package("foo", dir.path())];
+
+        write_elf(&packages[0].1, "/usr/lib/libfoo.so.1", ElfKind::SharedObject, Some("libfoo.so.1"), &[]);
+        write_elf(&packages[1].1, "/usr/bin/foo", ElfKind::Executable, None, &["libfoo.so.1", "libbar.so.2", "libprivate.so"]);
+        write_elf(&packages[1].1, "/usr/lib/foo/libprivate.so", ElfKind::SharedObject, Some("libprivate.so"), &["libfoo.so.1"]);
+
+        add_library_relations(&mut packages, &root).unwrap();
+
+        let (libfoo, foo) = (&packages[0].0, &packages[1].0);
+
+        assert_eq!(libfoo.provides.iter().map(ToString::to_string).collect::<Vec<_>>(), ["so:libfoo.so.1"]);
+        assert!(libfoo.dependencies.is_empty());
+
+        // libfoo.so.1 comes from the other package of the build, nothing provides libbar.so.2 and
+        // libprivate.so ships with foo
+        assert!(foo.provides.is_empty());
+        assert_eq!(foo.dependencies.iter().map(ToString::to_string).collect::<Vec<_>>(), ["so:libfoo.so.1"]);
+
+        // Relations are only added once
+        add_library_relations(&mut packages, &root).unwrap();
+        assert_eq!((packages[0].0.provides.len(), packages[1].0.dependencies.len()), (1, 1));
+    }
+}
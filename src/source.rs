@@ -0,0 +1,238 @@
+use std::{collections::HashSet, path::Path};
+use mlua::{Lua, Table, Value};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::file_operations::{download_file_blocking, extract_tarball, sha256sum_file};
+use crate::lua_functions::record_source_repository;
+use crate::path_utils::sanitize_path;
+
+/// Checksum of sources that cannot have one, such as git repositories
+pub const SKIP: &str = "SKIP";
+
+/// Prefix of the URLs of git sources, e.g. "git+https://github.com/ziglang/zig.git#tag=0.14.0"
+pub const GIT_PREFIX: &str = "git+";
+
+/// A file downloaded into SRC_DIR before PREPARE, as listed in the sources field of INFO
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Source {
+    pub url: String,
+    /// Expected SHA-256 of the file, None for git sources (SKIP)
+    pub sha256: Option<String>,
+    /// Name of the file or repository in SRC_DIR, the last component of the URL by default
+    pub filename: String,
+    /// Unpack the tarball into SRC_DIR once verified
+    pub extract: bool,
+}
+
+impl Source {
+    /// Returns true for git repositories, which are cloned instead of downloaded
+    pub fn is_vcs(&self) -> bool {
+        self.url.starts_with(GIT_PREFIX)
+    }
+}
+
+/// Read the sources field of INFO
+pub fn get_sources(info_table: &Table) -> Result<Vec<Source>> {
+    let invalid = |message: String| Error::InvalidPackage(format!("sources field: {}", message));
+
+    let table = match info_table.get::<Option<Table>>("sources") {
+        Ok(Some(table)) => table,
+        Ok(None) => return Ok(Vec::new()),
+        Err(_) => return Err(invalid("must be a list of tables".to_string())),
+    };
+
+    let mut sources: Vec<Source> = Vec::new();
+    let mut filenames = HashSet::new();
+
+    for entry in table.sequence_values::<Table>() {
+        let entry = entry.map_err(|_| invalid("must be a list of tables".to_string()))?;
+
+        let url = match entry.get::<Value>("url")? {
+            Value::String(url) => url.to_str()?.to_string(),
+            Value::Nil => return Err(invalid("url missing".to_string())),
+            _ => return Err(invalid("url must be a string".to_string())),
+        };
+
+        let is_vcs = url.starts_with(GIT_PREFIX);
+
+        if !is_vcs && !url.starts_with("https://") && !url.starts_with("http://") {
+            return Err(invalid(format!("{}: only http(s) and git+ URLs are supported", url)));
+        }
+
+        let sha256 = match entry.get::<Value>("sha256")? {
+            Value::String(sha256) if sha256 == SKIP && is_vcs => None,
+            Value::String(sha256) if sha256 == SKIP => return Err(invalid(format!("{}: SKIP is only allowed for git sources", url))),
+            Value::String(_) if is_vcs => return Err(invalid(format!("{}: the sha256 of a git source must be SKIP", url))),
+            Value::String(sha256) if sha256.as_bytes().len() == 64 && sha256.as_bytes().iter().all(u8::is_ascii_hexdigit) => Some(sha256.to_str()?.to_ascii_lowercase()),
+            Value::Nil => return Err(invalid(format!("{}: sha256 missing (SKIP for git sources)", url))),
+            _ => return Err(invalid(format!("{}: sha256 must be 64 hexadecimal digits", url))),
+        };
+
+        let filename = match entry.get::<Value>("filename")? {
+            Value::String(filename) => filename.to_str()?.to_string(),
+            Value::Nil => default_filename(&url),
+            _ => return Err(invalid(format!("{}: filename must be a string", url))),
+        };
+
+        if filename.is_empty() {
+            return Err(invalid(format!("{}: filename missing and not found in the URL", url)));
+        }
+
+        if !filenames.insert(filename.clone()) {
+            return Err(invalid(format!("{} is listed twice", filename)));
+        }
+
+        let extract = match entry.get::<Value>("extract")? {
+            Value::Boolean(true) if is_vcs => return Err(invalid(format!("{}: git sources cannot be extracted", url))),
+            Value::Boolean(extract) => extract,
+            Value::Nil => false,
+            _ => return Err(invalid(format!("{}: extract must be a boolean", url))),
+        };
+
+        sources.push(Source { url, sha256, filename, extract });
+    }
+
+    Ok(sources)
+}
+
+/// Last component of the path of a URL, without the .git suffix of repositories
+fn default_filename(url: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or(url).trim_end_matches('/');
+    let name = path.rsplit('/').next().unwrap_or(path);
+
+    match url.starts_with(GIT_PREFIX) {
+        true => name.trim_end_matches(".git").to_string(),
+        false => name.to_string(),
+    }
+}
+
+/// Download, verify and unpack the sources into src_dir
+///
+/// Files already downloaded are kept when their checksum matches, git sources already cloned are
+/// fetched again and checked out at the requested revision.
+pub fn fetch_sources(lua: &Lua, sources: &[Source], src_dir: &Path) -> Result<()> {
+    for source in sources {
+        let path = sanitize_path(src_dir, &source.filename)?;
+
+        let Some(expected) = &source.sha256 else {
+            fetch_git_source(lua, source, &path)?;
+            continue;
+        };
+
+        if path.is_file() && sha256sum_file(&path)? == *expected {
+            println!("Using downloaded {}", source.filename);
+        } else {
+            download_file_blocking(&source.url, src_dir, &source.filename)?;
+
+            let actual = sha256sum_file(&path)?;
+
+            if actual != *expected {
+                return Err(Error::ChecksumMismatch {
+                    filename: source.filename.clone(),
+                    url: source.url.clone(),
+                    expected: expected.clone(),
+                    actual,
+                });
+            }
+        }
+
+        if source.extract {
+            println!("Extracting {}", source.filename);
+            extract_tarball(&path, src_dir)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Clone or fetch a git source, checking out the tag, branch or commit named after # in its URL,
+/// the default branch of the remote otherwise
+fn fetch_git_source(lua: &Lua, source: &Source, path: &Path) -> Result<()> {
+    let url = source.url.trim_start_matches(GIT_PREFIX);
+
+    let (url, revision) = match url.split_once('#') {
+        Some((url, fragment)) => match fragment.split_once('=') {
+            Some(("tag", tag)) => (url, format!("refs/tags/{}", tag)),
+            Some(("branch", branch)) => (url, format!("origin/{}", branch)),
+            Some(("commit", commit)) => (url, commit.to_string()),
+            _ => return Err(Error::InvalidPackage(format!("sources field: {}: expected #tag=, #branch= or #commit=", source.url))),
+        },
+        None => (url, "origin/HEAD".to_string()),
+    };
+
+    let repo = if path.join(".git").exists() {
+        println!("Fetching {} into cloned {}", url, source.filename);
+        let repo = git2::Repository::open(path)?;
+        // Update the remote-tracking branches and tags the revision refers to, origin/HEAD follows
+        // the default branch
+        repo.remote_anonymous(url)?.fetch(&["+refs/heads/*:refs/remotes/origin/*", "+refs/tags/*:refs/tags/*"], None, None)?;
+        repo
+    } else {
+        println!("Cloning git repository from {} to {}", url, path.display());
+        git2::Repository::clone(url, path)?
+    };
+
+    let commit = repo.revparse_single(&revision)?.peel_to_commit()?;
+
+    repo.checkout_tree(commit.as_object(), Some(git2::build::CheckoutBuilder::new().force()))?;
+    repo.set_head_detached(commit.id())?;
+
+    record_source_repository(lua, &repo);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use mlua::Lua;
+
+    use super::{fetch_git_source, Source};
+
+    /// Commit file with contents on the current branch of repo
+    fn commit(repo: &git2::Repository, file: &str, contents: &str) {
+        let workdir = repo.workdir().unwrap();
+        fs::write(workdir.join(file), contents).unwrap();
+
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new(file)).unwrap();
+        index.write().unwrap();
+
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+        let parent = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+        let parents: Vec<_> = parent.iter().collect();
+
+        repo.commit(Some("HEAD"), &signature, &signature, contents, &tree, &parents).unwrap();
+    }
+
+    #[test]
+    fn cloned_repository_is_fetched_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let upstream = git2::Repository::init(dir.path().join("upstream")).unwrap();
+        commit(&upstream, "f", "one");
+
+        let branch = upstream.head().unwrap().shorthand().unwrap().to_string();
+        let url = format!("git+file://{}", dir.path().join("upstream").display());
+        let clone = dir.path().join("src").join("repo");
+        let lua = Lua::new();
+
+        let cases = [
+            (url.clone(), "two", "two"),
+            (format!("{}#branch={}", url, branch), "three", "three"),
+            (format!("{}#commit={}", url, upstream.head().unwrap().target().unwrap()), "four", "one"),
+        ];
+
+        for (url, next, expected) in cases {
+            let source = Source { url: url.clone(), sha256: None, filename: "repo".to_string(), extract: false };
+
+            fetch_git_source(&lua, &source, &clone).unwrap();
+            commit(&upstream, "f", next);
+            fetch_git_source(&lua, &source, &clone).unwrap();
+
+            assert_eq!(fs::read_to_string(clone.join("f")).unwrap(), expected, "{}", url);
+        }
+    }
+}
@@ -0,0 +1,206 @@
+/// SPDX license identifiers known to vrdpkg, other identifiers are reported as unknown
+pub const LICENSES: &[&str] = &[
+    "0BSD", "AFL-3.0", "AGPL-3.0-only", "AGPL-3.0-or-later", "Apache-1.1", "Apache-2.0", "APSL-2.0",
+    "Artistic-1.0", "Artistic-1.0-Perl", "Artistic-2.0", "BitTorrent-1.1", "BlueOak-1.0.0",
+    "BSD-1-Clause", "BSD-2-Clause", "BSD-2-Clause-Patent", "BSD-3-Clause", "BSD-3-Clause-Clear",
+    "BSD-4-Clause", "BSL-1.0", "bzip2-1.0.6", "CAL-1.0", "CC-BY-3.0", "CC-BY-4.0", "CC-BY-NC-4.0",
+    "CC-BY-NC-SA-4.0", "CC-BY-ND-4.0", "CC-BY-SA-3.0", "CC-BY-SA-4.0", "CC0-1.0", "CDDL-1.0",
+    "CDDL-1.1", "CECILL-2.1", "CPL-1.0", "curl", "ECL-2.0", "EPL-1.0", "EPL-2.0", "EUPL-1.1",
+    "EUPL-1.2", "FSFAP", "FSFUL", "FSFULLR", "FTL", "GFDL-1.1-only", "GFDL-1.1-or-later",
+    "GFDL-1.2-only", "GFDL-1.2-or-later", "GFDL-1.3-only", "GFDL-1.3-or-later", "GPL-1.0-only",
+    "GPL-1.0-or-later", "GPL-2.0-only", "GPL-2.0-or-later", "GPL-3.0-only", "GPL-3.0-or-later",
+    "HPND", "ICU", "IJG", "ImageMagick", "Imlib2", "Info-ZIP", "IPA", "IPL-1.0", "ISC",
+    "LGPL-2.0-only", "LGPL-2.0-or-later", "LGPL-2.1-only", "LGPL-2.1-or-later", "LGPL-3.0-only",
+    "LGPL-3.0-or-later", "Libpng", "libpng-2.0", "libtiff", "LPL-1.02", "LPPL-1.3c", "MIT", "MIT-0",
+    "MIT-CMU", "MirOS", "MPL-1.1", "MPL-2.0", "MPL-2.0-no-copyleft-exception", "MS-PL", "MS-RL",
+    "MulanPSL-2.0", "NCSA", "NTP", "OFL-1.0", "OFL-1.1", "OLDAP-2.8", "OpenSSL", "OSL-3.0",
+    "PHP-3.0", "PHP-3.01", "PostgreSQL", "PSF-2.0", "Python-2.0", "Qhull", "Ruby", "SGI-B-2.0",
+    "SISSL", "Sleepycat", "SMLNJ", "SSPL-1.0", "TCL", "UCL-1.0", "Unicode-3.0", "Unicode-DFS-2016",
+    "Unlicense", "UPL-1.0", "Vim", "W3C", "WTFPL", "X11", "XFree86-1.1", "Zlib",
+    "zlib-acknowledgement", "ZPL-2.0", "ZPL-2.1",
+];
+
+/// SPDX exceptions allowed after WITH
+pub const EXCEPTIONS: &[&str] = &[
+    "389-exception", "Autoconf-exception-2.0", "Autoconf-exception-3.0", "Bison-exception-2.2",
+    "Classpath-exception-2.0", "Font-exception-2.0", "GCC-exception-2.0", "GCC-exception-3.1",
+    "GPL-3.0-linking-exception", "LGPL-3.0-linking-exception", "Linux-syscall-note", "LLVM-exception",
+    "OCaml-LGPL-linking-exception", "OpenJDK-assembly-exception-1.0", "Qt-GPL-exception-1.0",
+    "Qt-LGPL-exception-1.1", "Swift-exception", "u-boot-exception-2.0",
+    "Universal-FOSS-exception-1.0", "WxWindows-exception-3.1",
+];
+
+/// Identifiers deprecated by SPDX in favour of their -only and -or-later forms
+const DEPRECATED: &[&str] = &[
+    "GPL-1.0", "GPL-2.0", "GPL-3.0", "LGPL-2.0", "LGPL-2.1", "LGPL-3.0", "AGPL-1.0", "AGPL-3.0",
+    "GFDL-1.1", "GFDL-1.2", "GFDL-1.3",
+];
+
+/// Parse an SPDX license expression such as "MIT OR (Apache-2.0 WITH LLVM-exception)"
+///
+/// Returns warnings about unknown or deprecated identifiers, or an error if the expression is
+/// malformed. LicenseRef- identifiers are accepted as they are.
+pub fn check_license_expression(expression: &str) -> Result<Vec<String>, String> {
+    let spaced = expression.replace('(', " ( ").replace(')', " ) ");
+    let tokens: Vec<&str> = spaced.split_whitespace().collect();
+
+    if tokens.is_empty() {
+        return Err("empty license expression".to_string());
+    }
+
+    let mut parser = Parser { tokens, position: 0, warnings: Vec::new() };
+    parser.or_expression()?;
+
+    match parser.tokens.get(parser.position) {
+        Some(token) => Err(format!("unexpected \"{}\"", token)),
+        None => Ok(parser.warnings),
+    }
+}
+
+struct Parser<'a> {
+    tokens: Vec<&'a str>,
+    position: usize,
+    warnings: Vec<String>,
+}
+
+impl<'a> Parser<'a> {
+    fn next(&mut self) -> Option<&'a str> {
+        let token = self.tokens.get(self.position).copied();
+        self.position += 1;
+        token
+    }
+
+    /// Consume the operator if it comes next, SPDX accepts operators in upper or lower case
+    fn operator(&mut self, operator: &str) -> bool {
+        let found = self.tokens.get(self.position)
+            .is_some_and(|token| *token == operator || *token == operator.to_lowercase());
+
+        if found {
+            self.position += 1;
+        }
+
+        found
+    }
+
+    fn or_expression(&mut self) -> Result<(), String> {
+        self.and_expression()?;
+
+        while self.operator("OR") {
+            self.and_expression()?;
+        }
+
+        Ok(())
+    }
+
+    fn and_expression(&mut self) -> Result<(), String> {
+        self.with_expression()?;
+
+        while self.operator("AND") {
+            self.with_expression()?;
+        }
+
+        Ok(())
+    }
+
+    fn with_expression(&mut self) -> Result<(), String> {
+        match self.next() {
+            Some("(") => {
+                self.or_expression()?;
+
+                if self.next() != Some(")") {
+                    return Err("missing \")\"".to_string());
+                }
+            }
+            Some(license) if is_operator(license) || license == ")" => return Err(format!("unexpected \"{}\"", license)),
+            Some(license) => self.license(license)?,
+            None => return Err("unexpected end of expression".to_string()),
+        }
+
+        if self.operator("WITH") {
+            match self.next() {
+                Some(exception) if is_identifier(exception) => {
+                    if !EXCEPTIONS.iter().any(|known| known.eq_ignore_ascii_case(exception)) {
+                        self.warnings.push(format!("{} is not a known SPDX license exception", exception));
+                    }
+                }
+                Some(token) => return Err(format!("\"{}\" is not a license exception", token)),
+                None => return Err("missing license exception after WITH".to_string()),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn license(&mut self, token: &str) -> Result<(), String> {
+        let license = token.strip_suffix('+').unwrap_or(token);
+
+        if license.starts_with("LicenseRef-") || license.starts_with("DocumentRef-") {
+            return Ok(());
+        }
+
+        if !is_identifier(license) {
+            return Err(format!("\"{}\" is not a license identifier", token));
+        }
+
+        if let Some(deprecated) = DEPRECATED.iter().find(|deprecated| deprecated.eq_ignore_ascii_case(license)) {
+            self.warnings.push(format!("{} is deprecated, use {}-only or {}-or-later", token, deprecated, deprecated));
+        } else if !LICENSES.iter().any(|known| known.eq_ignore_ascii_case(license)) {
+            self.warnings.push(format!("{} is not a known SPDX license identifier", license));
+        }
+
+        Ok(())
+    }
+}
+
+fn is_operator(token: &str) -> bool {
+    ["AND", "OR", "WITH"].iter().any(|operator| token == *operator || token == operator.to_lowercase())
+}
+
+fn is_identifier(token: &str) -> bool {
+    !token.is_empty() && token.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check_license_expression;
+
+    #[test]
+    fn valid_expressions() {
+        let cases: [(&str, &[&str]); 9] = [
+            ("MIT", &[]),
+            ("MIT OR (Apache-2.0 WITH LLVM-exception)", &[]),
+            ("(MIT AND Zlib) OR BSD-3-Clause", &[]),
+            ("mit or apache-2.0 with llvm-exception", &[]),
+            ("GPL-2.0-or-later WITH Classpath-exception-2.0 AND MIT", &[]),
+            ("LicenseRef-Proprietary AND MIT+", &[]),
+            ("GPL-2.0+", &["GPL-2.0+ is deprecated, use GPL-2.0-only or GPL-2.0-or-later"]),
+            ("Foo-1.0 OR MIT", &["Foo-1.0 is not a known SPDX license identifier"]),
+            ("MIT WITH Foo-exception", &["Foo-exception is not a known SPDX license exception"]),
+        ];
+
+        for (expression, warnings) in cases {
+            assert_eq!(check_license_expression(expression), Ok(warnings.iter().map(ToString::to_string).collect()), "{}", expression);
+        }
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        let cases = [
+            ("", "empty license expression"),
+            ("MIT AND", "unexpected end of expression"),
+            ("AND MIT", "unexpected \"AND\""),
+            ("MIT OR OR Zlib", "unexpected \"OR\""),
+            ("MIT Zlib", "unexpected \"Zlib\""),
+            ("(MIT OR Zlib", "missing \")\""),
+            ("MIT OR Zlib)", "unexpected \")\""),
+            ("()", "unexpected \")\""),
+            ("MIT WITH", "missing license exception after WITH"),
+            ("MIT WITH (", "\"(\" is not a license exception"),
+            ("MIT/X11", "\"MIT/X11\" is not a license identifier"),
+        ];
+
+        for (expression, error) in cases {
+            assert_eq!(check_license_expression(expression), Err(error.to_string()), "{}", expression);
+        }
+    }
+}
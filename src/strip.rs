@@ -0,0 +1,90 @@
+use std::{collections::HashSet, ffi::{OsStr, OsString}, fs, os::unix::fs::{MetadataExt, PermissionsExt}, path::Path};
+
+use crate::elf::{elf_files, run_tool, ElfKind};
+use crate::error::Result;
+use crate::path_utils::glob_match;
+
+/// Directory debuggers look for detached debug info in, relative to the root
+pub const DEBUG_DIR: &str = "usr/lib/debug";
+
+/// Strip the ELF files of a package directory, moving their debug info into debug_dir
+///
+/// Debug files are named after the build ID of the binary (.build-id/ab/cdef.debug), or after
+/// its path for binaries without one, and every binary gets a debuglink to its debug file.
+/// Files matching one of the no_strip globs are left untouched. Returns true if any debug info
+/// was written to debug_dir.
+pub fn strip_package(pkg_dir: &Path, debug_dir: &Path, no_strip: &[String]) -> Result<bool> {
+    let mut seen = HashSet::new();
+    let mut split = false;
+
+    for elf_file in elf_files(pkg_dir)? {
+        if no_strip.iter().any(|glob| glob_match(glob, &elf_file.path)) {
+            continue;
+        }
+
+        // Hard links share their contents, strip them once
+        let metadata = fs::metadata(&elf_file.file)?;
+
+        if !seen.insert((metadata.dev(), metadata.ino())) {
+            continue;
+        }
+
+        println!("Stripping {}", elf_file.path);
+
+        let file = elf_file.file.as_os_str();
+        let sections = run_tool("readelf", [OsStr::new("--wide"), OsStr::new("--notes"), OsStr::new("--section-headers"), file])?;
+
+        let debug_file = if sections.contains(" .debug_info ") && !sections.contains(" .gnu_debuglink ") {
+            let debug_file = match build_id(&sections) {
+                Some(build_id) if build_id.len() > 2 => debug_dir.join(DEBUG_DIR).join(".build-id").join(&build_id[..2]).join(format!("{}.debug", &build_id[2..])),
+                _ => debug_dir.join(DEBUG_DIR).join(format!("{}.debug", elf_file.path.trim_start_matches('/'))),
+            };
+
+            if let Some(parent) = debug_file.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            run_tool("objcopy", [OsStr::new("--only-keep-debug"), file, debug_file.as_os_str()])?;
+            fs::set_permissions(&debug_file, fs::Permissions::from_mode(0o644))?;
+
+            Some(debug_file)
+        } else {
+            None
+        };
+
+        // Read-only binaries are made writable while they are stripped
+        let mode = metadata.permissions().mode();
+
+        if mode & 0o200 == 0 {
+            fs::set_permissions(&elf_file.file, fs::Permissions::from_mode(mode | 0o200))?;
+        }
+
+        let strip_option = match elf_file.kind {
+            ElfKind::Executable => "--strip-all",
+            ElfKind::SharedObject => "--strip-unneeded",
+        };
+
+        run_tool("strip", [OsStr::new(strip_option), file])?;
+
+        if let Some(debug_file) = &debug_file {
+            let mut debuglink = OsString::from("--add-gnu-debuglink=");
+            debuglink.push(debug_file);
+
+            run_tool("objcopy", [debuglink.as_os_str(), file])?;
+            split = true;
+        }
+
+        if mode & 0o200 == 0 {
+            fs::set_permissions(&elf_file.file, fs::Permissions::from_mode(mode))?;
+        }
+    }
+
+    Ok(split)
+}
+
+/// Build ID of a binary, as printed by readelf --notes
+fn build_id(readelf_output: &str) -> Option<String> {
+    readelf_output.lines()
+        .find_map(|line| line.split_once("Build ID: "))
+        .map(|(_, build_id)| build_id.trim().to_string())
+}
@@ -0,0 +1,144 @@
+use std::{fs, path::{Path, PathBuf}};
+
+use crate::config::RepositoryConfig;
+use crate::dependency::validate_name;
+use crate::error::{Error, Result};
+use crate::file_operations::{download_file_blocking, sha256sum_file};
+use crate::repository::{IndexEntry, RepositoryIndex, INDEX_FILE};
+
+/// Directory of the downloaded indexes and package archives, relative to the root
+pub const CACHE_DIR: &str = "var/cache/vrdpkg";
+
+/// Where a repository is read from
+enum Location {
+    Directory(PathBuf),
+    Http(String),
+}
+
+impl Location {
+    fn parse(url: &str) -> Location {
+        if url.starts_with("http://") || url.starts_with("https://") {
+            Location::Http(url.trim_end_matches('/').to_string())
+        } else {
+            Location::Directory(PathBuf::from(url.strip_prefix("file://").unwrap_or(url)))
+        }
+    }
+
+    /// Copy or download a file of the repository into a directory
+    fn fetch(&self, filename: &str, dest_dir: &Path) -> Result<PathBuf> {
+        match self {
+            Location::Directory(dir) => {
+                fs::create_dir_all(dest_dir)?;
+
+                let dest_path = dest_dir.join(filename);
+                fs::copy(dir.join(filename), &dest_path)?;
+
+                Ok(dest_path)
+            }
+            Location::Http(url) => download_file_blocking(&format!("{}/{}", url, filename), dest_dir, filename),
+        }
+    }
+}
+
+/// A package available from one of the configured repositories
+#[derive(Clone)]
+pub struct SyncPackage {
+    pub repository: RepositoryConfig,
+    pub entry: IndexEntry,
+}
+
+/// Download the index of every repository into the cache under root
+pub fn sync_repositories(root: &Path, repositories: &[RepositoryConfig]) -> Result<()> {
+    if repositories.is_empty() {
+        println!("No repositories configured");
+        return Ok(());
+    }
+
+    let indexes_dir = root.join(CACHE_DIR).join("indexes");
+    fs::create_dir_all(&indexes_dir)?;
+
+    for repository in repositories {
+        let cached_index = cached_index_path(root, repository)?;
+
+        let download_dir = indexes_dir.join(format!("{}.download", repository.name));
+        let downloaded = Location::parse(&repository.url).fetch(INDEX_FILE, &download_dir)?;
+
+        // Only replace the cached index once the new one is known to be valid
+        let index = RepositoryIndex::parse(&fs::read_to_string(&downloaded)?)
+            .map_err(|e| Error::Repository(format!("{}: {}", repository.name, e)))?;
+
+        fs::rename(&downloaded, &cached_index)?;
+        fs::remove_dir_all(&download_dir)?;
+
+        println!("Synced {} ({} packages)", repository.name, index.packages.len());
+    }
+
+    Ok(())
+}
+
+/// Packages of every synced repository, in repository order
+pub fn load_sync_packages(root: &Path, repositories: &[RepositoryConfig]) -> Result<Vec<SyncPackage>> {
+    let mut packages = Vec::new();
+
+    for repository in repositories {
+        let cached_index = cached_index_path(root, repository)?;
+
+        if !cached_index.exists() {
+            eprintln!("Warning: repository {} has not been synced, run vrdpkg sync", repository.name);
+            continue;
+        }
+
+        let index = RepositoryIndex::parse(&fs::read_to_string(&cached_index)?)?;
+
+        packages.extend(index.packages.into_iter().map(|entry| SyncPackage {
+            repository: repository.clone(),
+            entry,
+        }));
+    }
+
+    Ok(packages)
+}
+
+/// Download a package archive into the cache under root, checking it against the index
+///
+/// An archive already in the cache is reused when its hash matches.
+pub fn fetch_package(root: &Path, package: &SyncPackage) -> Result<PathBuf> {
+    let entry = &package.entry;
+
+    if Path::new(&entry.filename).file_name() != Some(entry.filename.as_ref()) || entry.filename.starts_with('.') {
+        return Err(Error::Repository(format!("{}: invalid file name {:?}", package.repository.name, entry.filename)));
+    }
+
+    let packages_dir = root.join(CACHE_DIR).join("packages");
+    let cached = packages_dir.join(&entry.filename);
+
+    if cached.is_file() && sha256sum_file(&cached)? == entry.sha256 {
+        return Ok(cached);
+    }
+
+    println!("Fetching {} from {}", entry.filename, package.repository.name);
+
+    let path = Location::parse(&package.repository.url).fetch(&entry.filename, &packages_dir)?;
+
+    let size = fs::metadata(&path)?.len();
+    let sha256 = sha256sum_file(&path)?;
+
+    if size != entry.size || sha256 != entry.sha256 {
+        fs::remove_file(&path)?;
+
+        return Err(Error::Repository(format!(
+            "{} does not match the index of {} (expected {} bytes with sha256 {}, got {} bytes with sha256 {})",
+            entry.filename, package.repository.name, entry.size, entry.sha256, size, sha256,
+        )));
+    }
+
+    Ok(path)
+}
+
+/// Location of the cached index of a repository
+fn cached_index_path(root: &Path, repository: &RepositoryConfig) -> Result<PathBuf> {
+    validate_name(&repository.name)
+        .map_err(|e| Error::Repository(format!("invalid repository name {:?}: {}", repository.name, e)))?;
+
+    Ok(root.join(CACHE_DIR).join("indexes").join(format!("{}.json", repository.name)))
+}
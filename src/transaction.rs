@@ -0,0 +1,665 @@
+use std::{collections::{BTreeSet, HashMap, HashSet}, fs, io::{self, BufRead, Write}, path::{Path, PathBuf}};
+use serde::{Deserialize, Serialize};
+
+use crate::archive::{is_metadata_file, open_package_archive};
+use crate::database::{write_package, Database, InstalledPackage};
+use crate::error::{Error, Result};
+use crate::history;
+use crate::install::install_path;
+use crate::package_info::PackageArchive;
+use crate::path_utils::resolve_in_root;
+use crate::scriptlet::run_hook;
+use crate::trigger::run_triggers;
+
+/// Name of the journal of the running transaction, in the database directory
+pub const JOURNAL_FILE: &str = "journal";
+
+/// Name of the lock file held while a transaction runs, in the database directory
+pub const LOCK_FILE: &str = "lock";
+
+/// Suffix of files staged next to their target until the transaction commits
+const STAGED_SUFFIX: &str = "vrdpkg-new";
+
+/// Suffix of replaced or removed files kept next to their target until the transaction commits
+const BACKUP_SUFFIX: &str = "vrdpkg-old";
+
+/// One line of the journal, written before the change it describes is made
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum JournalEntry {
+    /// A file, symlink or directory was created, rolled back by deleting it
+    Created { path: PathBuf },
+    /// A staging file or directory was created, rolled back by deleting it with its contents
+    Staged { path: PathBuf },
+    /// A staged file or directory was moved into place, rolled back by deleting it with its contents
+    Installed { path: PathBuf },
+    /// An existing path was moved aside, rolled back by moving it back and deleted on commit
+    Backup { path: PathBuf, backup: PathBuf },
+    /// A path was moved to a new name it keeps after the commit, rolled back by moving it back
+    Renamed { from: PathBuf, to: PathBuf },
+    /// A directory to delete on commit if it is empty
+    Prune { path: PathBuf },
+    /// A line for the history log, written on commit
+    History { message: String },
+    /// Every change is in place, from here on the transaction is completed instead of rolled back
+    Commit,
+}
+
+/// A change applied when the transaction commits, once everything has been staged
+enum Action {
+    /// Move a staged file or directory over its target, keeping the previous one as a backup
+    Install { staged: PathBuf, target: PathBuf },
+    /// Move a file, symlink or database entry aside
+    Remove { target: PathBuf },
+    /// Give a file a new name, replacing what had that name
+    Rename { from: PathBuf, to: PathBuf },
+    /// Delete a directory if it is empty
+    Prune { target: PathBuf },
+}
+
+/// A scriptlet hook to run once every file of the transaction is in place
+struct QueuedHook {
+    package: String,
+    scriptlets: String,
+    hook: &'static str,
+    versions: Vec<String>,
+}
+
+/// A set of changes to a root that is applied completely or not at all
+///
+/// Package files are first unpacked next to their target under a temporary name, the commit then
+/// moves them in place with renames. Every step is recorded in a journal under the database
+/// directory beforehand, so a failure, or a crash detected by the next run, rolls the root back
+/// to its previous state. A lock file stops two processes from changing the same root at once.
+pub struct Transaction {
+    root: PathBuf,
+    database: Database,
+    journal: fs::File,
+    actions: Vec<Action>,
+    /// Installed packages as they will be once the transaction commits
+    installed: Vec<InstalledPackage>,
+    /// Targets of the planned removals
+    removed: HashSet<PathBuf>,
+    /// Post-install, post-upgrade and post-remove hooks, in the order they were queued
+    hooks: Vec<QueuedHook>,
+    /// Manifest paths of the packages added and removed, directories included, matched against
+    /// the triggers
+    changed: BTreeSet<String>,
+    /// Held for the lifetime of the transaction, the lock is released when it is closed
+    _lock: fs::File,
+}
+
+impl Transaction {
+    /// Run a transaction on root: the closure stages changes, which are committed if it succeeds
+    /// and rolled back otherwise
+    pub fn run(root: &Path, stage: impl FnOnce(&mut Transaction) -> Result<()>) -> Result<()> {
+        let mut transaction = Transaction::begin(root)?;
+
+        let result = stage(&mut transaction).and_then(|()| transaction.commit());
+
+        if let Err(e) = result {
+            match transaction.rollback() {
+                Ok(true) => eprintln!("Transaction rolled back"),
+                Ok(false) => {}
+                Err(rollback_error) => eprintln!("Warning: rollback failed, it will be retried by the next run: {}", rollback_error),
+            }
+
+            return Err(e);
+        }
+
+        transaction.finish()
+    }
+
+    /// Lock the root, recover from an interrupted transaction and start a new journal
+    fn begin(root: &Path) -> Result<Transaction> {
+        let database = Database::open(root);
+        fs::create_dir_all(database.dir())?;
+
+        let lock = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(database.dir().join(LOCK_FILE))?;
+
+        match lock.try_lock() {
+            Ok(()) => {}
+            Err(fs::TryLockError::WouldBlock) => return Err(Error::Locked(root.to_path_buf())),
+            Err(fs::TryLockError::Error(e)) => return Err(e.into()),
+        }
+
+        recover(&database)?;
+
+        let journal = fs::File::create(database.dir().join(JOURNAL_FILE))?;
+        sync_dir(&database.dir())?;
+
+        Ok(Transaction {
+            root: root.to_path_buf(),
+            installed: database.packages()?,
+            database,
+            journal,
+            actions: Vec::new(),
+            removed: HashSet::new(),
+            hooks: Vec::new(),
+            changed: BTreeSet::new(),
+            _lock: lock,
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Installed packages as they will be once the transaction commits
+    pub fn installed(&self) -> &[InstalledPackage] {
+        &self.installed
+    }
+
+    /// Returns true if the transaction removes the path, a file at a removed path does not conflict
+    pub fn is_removed(&self, target: &Path) -> bool {
+        self.removed.contains(target)
+    }
+
+    /// Unpack the entries of a package archive next to their targets
+    ///
+    /// Missing directories are created right away so files can be staged inside them, directories
+    /// that already exist keep their permissions. Files found in redirects are installed at the
+    /// given location instead, or not at all for None.
+    pub fn stage_archive(&mut self, package: &PackageArchive, redirects: &HashMap<&str, Option<PathBuf>>) -> Result<()> {
+        let mut archive = open_package_archive(&package.path)?;
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let path = entry.path()?.into_owned();
+
+            if is_metadata_file(&path) {
+                continue;
+            }
+
+            let package_path = format!("/{}", path.to_string_lossy().trim_end_matches('/'));
+            let mut target = install_path(&self.root, &package_path)?;
+
+            match redirects.get(package_path.as_str()) {
+                Some(Some(redirect)) => target = redirect.clone(),
+                Some(None) => continue,
+                None => {}
+            }
+
+            entry.set_preserve_permissions(true);
+            entry.set_preserve_mtime(true);
+
+            if entry.header().entry_type().is_dir() {
+                if resolve_in_root(&self.root, &path)?.is_dir() {
+                    continue;
+                }
+
+                self.write_journal(&JournalEntry::Created { path: target.clone() })?;
+                entry.unpack(&target)?;
+                continue;
+            }
+
+            if fs::symlink_metadata(&target).is_ok_and(|metadata| metadata.is_dir()) {
+                return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{:?} is a directory", target)).into());
+            }
+
+            let staged = sibling(&target, STAGED_SUFFIX);
+            self.write_journal(&JournalEntry::Staged { path: staged.clone() })?;
+            remove_path(&staged)?;
+
+            entry.unpack(&staged)?;
+            self.actions.push(Action::Install { staged, target });
+        }
+
+        Ok(())
+    }
+
+    /// Remove a file or symlink of a package, installed at target, on commit
+    pub fn remove_file(&mut self, target: PathBuf) {
+        self.removed.insert(target.clone());
+        self.actions.push(Action::Remove { target });
+    }
+
+    /// Move a file of a package, installed at from, to a new name on commit instead of removing it
+    pub fn rename_file(&mut self, from: PathBuf, to: PathBuf) {
+        self.removed.insert(from.clone());
+        self.actions.push(Action::Rename { from, to });
+    }
+
+    /// Delete a directory on commit if nothing is left in it
+    pub fn prune_dir(&mut self, target: PathBuf) {
+        self.actions.push(Action::Prune { target });
+    }
+
+    /// Record a package in the database on commit, replacing the entry with the same name
+    pub fn add_package(&mut self, package: &PackageArchive) -> Result<()> {
+        let target = self.database.package_dir(&package.info.name)?;
+        let staged = sibling(&target, STAGED_SUFFIX);
+
+        let installed = InstalledPackage {
+            info: package.info.clone(),
+            manifest: package.manifest.clone(),
+            install: package.install.clone(),
+        };
+
+        self.write_journal(&JournalEntry::Staged { path: staged.clone() })?;
+        remove_path(&staged)?;
+        write_package(&staged, &installed)?;
+
+        self.actions.push(Action::Install { staged, target });
+
+        self.changed.extend(installed.manifest.entries.iter().map(|entry| entry.path.clone()));
+        self.installed.retain(|other| other.info.name != installed.info.name);
+        self.installed.push(installed);
+
+        Ok(())
+    }
+
+    /// Drop a package from the database on commit
+    pub fn remove_package(&mut self, name: &str) -> Result<()> {
+        let target = self.database.package_dir(name)?;
+
+        self.actions.push(Action::Remove { target });
+
+        if let Some(package) = self.installed.iter().find(|package| package.info.name == name) {
+            self.changed.extend(package.manifest.entries.iter().map(|entry| entry.path.clone()));
+        }
+
+        self.installed.retain(|package| package.info.name != name);
+
+        Ok(())
+    }
+
+    /// Run a hook of the install scriptlets of a package right away, a failure aborts the transaction
+    pub fn run_hook(&self, package: &str, scriptlets: Option<&str>, hook: &'static str, versions: &[String]) -> Result<()> {
+        match scriptlets {
+            Some(scriptlets) => run_hook(&self.root, package, scriptlets, hook, versions),
+            None => Ok(()),
+        }
+    }
+
+    /// Run a hook of the install scriptlets of a package once the files are in place, before the
+    /// transaction is committed
+    pub fn queue_hook(&mut self, package: &str, scriptlets: Option<&str>, hook: &'static str, versions: &[String]) {
+        if let Some(scriptlets) = scriptlets {
+            self.hooks.push(QueuedHook {
+                package: package.to_string(),
+                scriptlets: scriptlets.to_string(),
+                hook,
+                versions: versions.to_vec(),
+            });
+        }
+    }
+
+    /// Add a line to the history log once the transaction commits
+    pub fn record(&mut self, message: String) -> Result<()> {
+        self.write_journal(&JournalEntry::History { message })
+    }
+
+    /// Move every staged file in place, apply the removals and run the queued hooks, journaling
+    /// each step
+    fn commit(&mut self) -> Result<()> {
+        for action in std::mem::take(&mut self.actions) {
+            match action {
+                Action::Install { staged, target } => {
+                    self.backup(&target)?;
+                    self.write_journal(&JournalEntry::Installed { path: target.clone() })?;
+                    fs::rename(&staged, &target)?;
+                    sync_parent(&target)?;
+                }
+                Action::Remove { target } => self.backup(&target)?,
+                Action::Rename { from, to } => {
+                    self.backup(&to)?;
+                    self.write_journal(&JournalEntry::Renamed { from: from.clone(), to: to.clone() })?;
+                    fs::rename(&from, &to)?;
+                    sync_parent(&to)?;
+                }
+                Action::Prune { target } => self.write_journal(&JournalEntry::Prune { path: target })?,
+            }
+        }
+
+        for hook in std::mem::take(&mut self.hooks) {
+            run_hook(&self.root, &hook.package, &hook.scriptlets, hook.hook, &hook.versions)?;
+        }
+
+        self.write_journal(&JournalEntry::Commit)?;
+
+        Ok(())
+    }
+
+    /// Move an existing target aside so it can be restored by a rollback
+    fn backup(&mut self, target: &Path) -> Result<()> {
+        if fs::symlink_metadata(target).is_err() {
+            return Ok(());
+        }
+
+        let backup = sibling(target, BACKUP_SUFFIX);
+        remove_path(&backup)?;
+
+        self.write_journal(&JournalEntry::Backup { path: target.to_path_buf(), backup: backup.clone() })?;
+        fs::rename(target, &backup)?;
+        sync_parent(&backup)?;
+
+        Ok(())
+    }
+
+    /// Complete the committed transaction: delete the backups, prune directories, log history and
+    /// run the triggers watching the changed paths
+    fn finish(self) -> Result<()> {
+        complete(&self.database, &read_journal(&self.database)?)?;
+
+        run_triggers(&self.root, &self.changed)
+    }
+
+    /// Undo every journaled change of the transaction, returns false if nothing had been changed
+    fn rollback(&mut self) -> Result<bool> {
+        self.journal.flush()?;
+
+        let entries = read_journal(&self.database)?;
+        roll_back(&self.database, &entries)?;
+
+        Ok(!entries.is_empty())
+    }
+
+    /// Append an entry to the journal and flush it to disk, so it is never missing after a crash
+    /// that kept the change it describes
+    fn write_journal(&mut self, entry: &JournalEntry) -> Result<()> {
+        writeln!(self.journal, "{}", serde_json::to_string(entry)?)?;
+        self.journal.sync_data()?;
+
+        Ok(())
+    }
+}
+
+/// Complete or undo a transaction left behind by a process that did not finish
+fn recover(database: &Database) -> Result<()> {
+    let journal_path = database.dir().join(JOURNAL_FILE);
+
+    if !journal_path.exists() {
+        return Ok(());
+    }
+
+    let entries = read_journal(database)?;
+
+    if entries.iter().any(|entry| matches!(entry, JournalEntry::Commit)) {
+        println!("Completing an interrupted transaction");
+        complete(database, &entries)
+    } else {
+        println!("Rolling back an interrupted transaction");
+        roll_back(database, &entries)
+    }
+}
+
+/// Delete the backups, prune directories and write the history of a committed journal
+///
+/// Every step can be repeated, so an interrupted completion is simply run again.
+fn complete(database: &Database, entries: &[JournalEntry]) -> Result<()> {
+    let mut messages = Vec::new();
+
+    for entry in entries {
+        match entry {
+            JournalEntry::Backup { backup, .. } => remove_path(backup)?,
+            JournalEntry::Prune { path } => match fs::remove_dir(path) {
+                Ok(()) => {}
+                Err(e) if matches!(e.kind(), io::ErrorKind::NotFound | io::ErrorKind::DirectoryNotEmpty | io::ErrorKind::NotADirectory) => {}
+                Err(e) => return Err(e.into()),
+            },
+            JournalEntry::History { message } => messages.push(message),
+            JournalEntry::Created { .. } | JournalEntry::Staged { .. } | JournalEntry::Installed { .. } | JournalEntry::Renamed { .. } | JournalEntry::Commit => {}
+        }
+    }
+
+    for message in messages {
+        history::record(database, message)?;
+    }
+
+    fs::remove_file(database.dir().join(JOURNAL_FILE))?;
+
+    Ok(())
+}
+
+/// Undo the changes of a journal, newest first
+fn roll_back(database: &Database, entries: &[JournalEntry]) -> Result<()> {
+    for entry in entries.iter().rev() {
+        match entry {
+            JournalEntry::Created { path } => {
+                let result = match fs::symlink_metadata(path) {
+                    Ok(metadata) if metadata.is_dir() => fs::remove_dir(path),
+                    Ok(_) => fs::remove_file(path),
+                    Err(_) => Ok(()),
+                };
+
+                match result {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == io::ErrorKind::DirectoryNotEmpty => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            JournalEntry::Staged { path } | JournalEntry::Installed { path } => remove_path(path)?,
+            JournalEntry::Backup { path, backup } => {
+                if fs::symlink_metadata(backup).is_ok() {
+                    remove_path(path)?;
+                    fs::rename(backup, path)?;
+                    sync_parent(path)?;
+                }
+            }
+            JournalEntry::Renamed { from, to } => {
+                if fs::symlink_metadata(to).is_ok() {
+                    fs::rename(to, from)?;
+                    sync_parent(from)?;
+                }
+            }
+            JournalEntry::Prune { .. } | JournalEntry::History { .. } | JournalEntry::Commit => {}
+        }
+    }
+
+    fs::remove_file(database.dir().join(JOURNAL_FILE))?;
+
+    Ok(())
+}
+
+/// Read the journal, ignoring a last line cut short by a crash
+fn read_journal(database: &Database) -> Result<Vec<JournalEntry>> {
+    let file = fs::File::open(database.dir().join(JOURNAL_FILE))?;
+    let mut entries = Vec::new();
+
+    for line in io::BufReader::new(file).lines() {
+        match serde_json::from_str(&line?) {
+            Ok(entry) => entries.push(entry),
+            Err(_) => break,
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Hidden path next to a target, e.g. "/usr/bin/.zig.vrdpkg-new" for "/usr/bin/zig"
+fn sibling(target: &Path, suffix: &str) -> PathBuf {
+    let name = target.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+
+    target.with_file_name(format!(".{}.{}", name, suffix))
+}
+
+/// Flush the entries of a directory to disk, making the files created or renamed in it durable
+fn sync_dir(dir: &Path) -> Result<()> {
+    fs::File::open(dir)?.sync_all()?;
+
+    Ok(())
+}
+
+/// Flush the directory containing a path, after the path was renamed into it
+fn sync_parent(path: &Path) -> Result<()> {
+    match path.parent() {
+        Some(parent) => sync_dir(parent),
+        None => Ok(()),
+    }
+}
+
+/// Delete a file, symlink or directory tree if it exists
+fn remove_path(path: &Path) -> Result<()> {
+    let result = match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(_) => Ok(()),
+    };
+
+    Ok(result?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, fs, os::unix::fs::PermissionsExt, path::{Path, PathBuf}};
+
+    use super::{Action, Transaction};
+    use crate::archive::{create_package_archive, ArchiveOptions};
+    use crate::database::Database;
+    use crate::install::install_package;
+    use crate::manifest::Manifest;
+    use crate::package_info::{FinalPackageInfo, PackageArchive};
+
+    /// Build a package archive in dir from a list of (path, contents)
+    fn build_package(dir: &Path, name: &str, version: &str, files: &[(&str, &str)]) -> PackageArchive {
+        let pkg_dir = dir.join(format!("{}-{}", name, version));
+
+        for (path, contents) in files {
+            let file = pkg_dir.join(path.trim_start_matches('/'));
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(file, contents).unwrap();
+        }
+
+        let manifest = Manifest::from_dir(&pkg_dir).unwrap();
+
+        let info = FinalPackageInfo::bare(name, version);
+
+        fs::write(pkg_dir.join(".pkgfiles"), manifest.to_string()).unwrap();
+        fs::write(pkg_dir.join("package.json"), serde_json::to_string(&info).unwrap()).unwrap();
+
+        let path = dir.join(format!("{}-{}.tar.zst", name, version));
+        create_package_archive(&pkg_dir, &path, &ArchiveOptions::default()).unwrap();
+
+        PackageArchive::open(&path).unwrap()
+    }
+
+    /// Every path under root with its type, mode and contents
+    fn snapshot(root: &Path) -> BTreeMap<PathBuf, String> {
+        walkdir::WalkDir::new(root).min_depth(1).into_iter()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                let metadata = entry.path().symlink_metadata().unwrap();
+                let mode = metadata.permissions().mode();
+
+                let description = if metadata.is_dir() {
+                    format!("dir {:o}", mode)
+                } else if metadata.is_symlink() {
+                    format!("symlink {}", fs::read_link(entry.path()).unwrap().display())
+                } else {
+                    format!("file {:o} {}", mode, fs::read_to_string(entry.path()).unwrap_or_default())
+                };
+
+                (entry.path().strip_prefix(root).unwrap().to_path_buf(), description)
+            })
+            .collect()
+    }
+
+    /// A root with version 1.0.0 of a package installed, and an archive of version 2.0.0 that
+    /// changes a file, adds a file in a new directory and drops a file
+    fn setup(dir: &Path) -> (PathBuf, PackageArchive) {
+        let root = dir.join("root");
+        fs::create_dir(&root).unwrap();
+
+        let old = build_package(dir, "tool", "1.0.0", &[
+            ("/usr/bin/tool", "v1"),
+            ("/usr/share/tool/data", "data v1"),
+            ("/usr/share/tool/old", "only in v1"),
+        ]);
+        let new = build_package(dir, "tool", "2.0.0", &[
+            ("/usr/bin/tool", "v2"),
+            ("/usr/share/tool/data", "data v2"),
+            ("/usr/share/tool-extra/readme", "new in v2"),
+        ]);
+
+        Transaction::run(&root, |transaction| install_package(transaction, &old, false)).unwrap();
+
+        (root, new)
+    }
+
+    /// Stage the package, plus an action failing after every other change has been made
+    fn stage_failing_install(transaction: &mut Transaction, package: &PackageArchive, upgrade: bool) -> crate::error::Result<()> {
+        install_package(transaction, package, upgrade)?;
+
+        let root = transaction.root().to_path_buf();
+        transaction.actions.push(Action::Install { staged: root.join("missing"), target: root.join("usr/bin/late") });
+
+        Ok(())
+    }
+
+    fn assert_installed_version(root: &Path, version: &str) {
+        let packages = Database::open(root).packages().unwrap();
+
+        assert_eq!(packages.len(), 1);
+        assert_eq!(packages[0].info.version.to_string(), version);
+    }
+
+    #[test]
+    fn failed_commit_restores_previous_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let (root, new) = setup(dir.path());
+        let before = snapshot(&root);
+
+        let result = Transaction::run(&root, |transaction| stage_failing_install(transaction, &new, true));
+
+        assert!(result.is_err());
+        assert_eq!(snapshot(&root), before);
+        assert_installed_version(&root, "1.0.0");
+
+        // A new package, its database entry is a new directory moved into place
+        let other = build_package(dir.path(), "other", "1.0.0", &[("/usr/bin/other", "other")]);
+        let result = Transaction::run(&root, |transaction| stage_failing_install(transaction, &other, false));
+
+        assert!(result.is_err());
+        assert_eq!(snapshot(&root), before);
+        assert_installed_version(&root, "1.0.0");
+
+        // The same upgrade goes through once nothing fails
+        Transaction::run(&root, |transaction| install_package(transaction, &new, true)).unwrap();
+
+        assert_eq!(fs::read_to_string(root.join("usr/bin/tool")).unwrap(), "v2");
+        assert!(!root.join("usr/share/tool/old").exists());
+        assert_installed_version(&root, "2.0.0");
+    }
+
+    #[test]
+    fn interrupted_transaction_is_rolled_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let (root, new) = setup(dir.path());
+        let before = snapshot(&root);
+
+        // Stop in the middle of the commit without rolling back, as a crash would
+        let mut transaction = Transaction::begin(&root).unwrap();
+        stage_failing_install(&mut transaction, &new, true).unwrap();
+        assert!(transaction.commit().is_err());
+        drop(transaction);
+
+        let interrupted = snapshot(&root);
+        assert_eq!(fs::read_to_string(root.join("usr/bin/tool")).unwrap(), "v2");
+        assert!(interrupted.keys().any(|path| path.to_string_lossy().ends_with(".vrdpkg-old")));
+
+        // The next transaction finds the journal and restores the previous tree first
+        Transaction::run(&root, |_| Ok(())).unwrap();
+
+        assert_eq!(snapshot(&root), before);
+        assert_installed_version(&root, "1.0.0");
+    }
+
+    #[test]
+    fn changed_paths_come_from_manifests() {
+        let dir = tempfile::tempdir().unwrap();
+        let (root, new) = setup(dir.path());
+
+        let mut transaction = Transaction::begin(&root).unwrap();
+        install_package(&mut transaction, &new, true).unwrap();
+
+        // Paths of the new version, directories included, and the ones only the old version had
+        for path in ["/usr", "/usr/bin/tool", "/usr/share/tool-extra", "/usr/share/tool-extra/readme", "/usr/share/tool/old"] {
+            assert!(transaction.changed.contains(path), "{} missing from {:?}", path, transaction.changed);
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use std::{collections::{BTreeMap, BTreeSet}, fs, path::Path};
+use serde::Deserialize;
+
+use crate::error::Result;
+use crate::file_operations::execute_in_root;
+use crate::path_utils::{glob_match, resolve_in_root};
+
+/// Directories holding trigger files, relative to the root
+///
+/// Packages ship triggers in the first one, a trigger of the second one replaces the packaged
+/// trigger with the same file name.
+pub const TRIGGER_DIRS: [&str; 2] = ["usr/share/vrdpkg/triggers", "etc/vrdpkg/triggers"];
+
+/// An action shared by packages, run once after a transaction that changed a matching path
+///
+/// Stored as `<name>.json` in one of the trigger directories, e.g.
+/// `{"description": "Updating the linker cache", "paths": ["usr/lib/*.so*"], "command": "ldconfig"}`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Trigger {
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Globs of paths relative to the root: `*` and `?` stay inside a directory, `**` matches
+    /// any number of directories
+    pub paths: Vec<String>,
+    /// Shell command run inside the root
+    pub command: String,
+}
+
+impl Trigger {
+    /// Returns true if the trigger watches the path (relative to the root or starting with "/")
+    pub fn matches(&self, path: &str) -> bool {
+        let path = path.trim_start_matches('/');
+
+        self.paths.iter().any(|glob| glob_match(glob.trim_start_matches('/'), path))
+    }
+}
+
+/// Every trigger installed under root, by name
+///
+/// Trigger files that cannot be read are skipped with a warning.
+pub fn load_triggers(root: &Path) -> Result<BTreeMap<String, Trigger>> {
+    let mut triggers = BTreeMap::new();
+
+    for dir in TRIGGER_DIRS {
+        let dir = resolve_in_root(root, Path::new(dir))?;
+
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+
+        for entry in entries {
+            let path = entry?.path();
+
+            let Some(name) = path.file_name().and_then(|name| name.to_str()).and_then(|name| name.strip_suffix(".json")) else {
+                continue;
+            };
+
+            let trigger = fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|content| serde_json::from_str::<Trigger>(&content).map_err(|e| e.to_string()));
+
+            match trigger {
+                Ok(trigger) => {
+                    triggers.insert(name.to_string(), trigger);
+                }
+                Err(e) => eprintln!("Warning: skipping trigger {:?}: {}", path, e),
+            }
+        }
+    }
+
+    Ok(triggers)
+}
+
+/// Run every trigger watching one of the changed paths once, in name order
+///
+/// The transaction is already committed, so failing triggers are reported as warnings.
+pub fn run_triggers(root: &Path, changed: &BTreeSet<String>) -> Result<()> {
+    if changed.is_empty() {
+        return Ok(());
+    }
+
+    for (name, trigger) in load_triggers(root)? {
+        if !changed.iter().any(|path| trigger.matches(path)) {
+            continue;
+        }
+
+        match &trigger.description {
+            Some(description) => println!("Running trigger {}: {}", name, description),
+            None => println!("Running trigger {}", name),
+        }
+
+        if let Err(e) = execute_in_root(root, &trigger.command) {
+            eprintln!("Warning: trigger {} failed: {}", name, e);
+        }
+    }
+
+    Ok(())
+}
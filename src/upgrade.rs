@@ -0,0 +1,106 @@
+use std::path::Path;
+
+use crate::database::{Database, InstalledPackage};
+use crate::dependency::Dependency;
+use crate::error::{Error, Result};
+use crate::install::{install_package, open_sync_package};
+use crate::resolver::{supersedes, Resolver};
+use crate::sync::SyncPackage;
+use crate::transaction::Transaction;
+
+/// Upgrade installed packages to the newest versions of the synced repositories
+///
+/// Only the named packages are considered, or every installed package if none are named. A
+/// package replaced by a repository package (a renamed package) is swapped for it. New
+/// dependencies are installed along the way, the plan is only printed with `dry_run`.
+pub fn upgrade_packages(root: &Path, names: &[String], repositories: &[SyncPackage], dry_run: bool) -> Result<()> {
+    let installed = Database::open(root).packages()?;
+
+    let mut targets: Vec<&InstalledPackage> = Vec::new();
+
+    for name in names {
+        match installed.iter().find(|package| &package.info.name == name) {
+            Some(package) => targets.push(package),
+            None => return Err(Error::NotInstalled(name.clone())),
+        }
+    }
+
+    if names.is_empty() {
+        targets.extend(installed.iter());
+    }
+
+    let mut requests: Vec<Dependency> = Vec::new();
+
+    for target in targets {
+        let Some(sync_package) = newest_replacement(target, repositories) else {
+            continue;
+        };
+
+        let info = &sync_package.entry.info;
+        let request = Dependency::exact(&info.name, &info.version);
+
+        if !requests.contains(&request) {
+            requests.push(request);
+        }
+    }
+
+    if requests.is_empty() {
+        println!("Nothing to upgrade");
+        return Ok(());
+    }
+
+    let plan = Resolver::new(repositories.iter().map(|package| &package.entry.info), installed.iter().map(|package| &package.info))
+        .resolve(&requests)?;
+
+    println!("Upgrade plan:");
+
+    for &index in &plan {
+        let info = &repositories[index].entry.info;
+        let superseded: Vec<&InstalledPackage> = installed.iter()
+            .filter(|package| supersedes(info, &package.info))
+            .collect();
+
+        if superseded.is_empty() {
+            println!("  install {}", info.display_name());
+        }
+
+        for old in superseded {
+            if old.info.name == info.name {
+                println!("  upgrade {} {} -> {}", info.name, old.info.version, info.version);
+            } else {
+                println!("  replace {} with {}", old.info.display_name(), info.display_name());
+            }
+        }
+    }
+
+    if dry_run {
+        return Ok(());
+    }
+
+    let packages = plan.iter()
+        .map(|&index| open_sync_package(root, &repositories[index]))
+        .collect::<Result<Vec<_>>>()?;
+
+    Transaction::run(root, |transaction| {
+        for package in &packages {
+            install_package(transaction, package, true)?;
+        }
+
+        Ok(())
+    })
+}
+
+/// The repository package an installed package should be upgraded to, if there is a newer one
+///
+/// Packages declaring that they replace the installed package win over newer versions of it.
+fn newest_replacement<'a>(installed: &InstalledPackage, repositories: &'a [SyncPackage]) -> Option<&'a SyncPackage> {
+    let info = &installed.info;
+
+    let replacement = repositories.iter()
+        .filter(|package| package.entry.info.name != info.name && supersedes(&package.entry.info, info))
+        .max_by(|a, b| a.entry.info.version.cmp(&b.entry.info.version));
+
+    replacement.or_else(|| repositories.iter()
+        .filter(|package| package.entry.info.name == info.name && package.entry.info.version > info.version)
+        .max_by(|a, b| a.entry.info.version.cmp(&b.entry.info.version)))
+}
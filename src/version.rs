@@ -0,0 +1,279 @@
+use std::{cmp::Ordering, fmt, str::FromStr};
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+/// A package version written as `[epoch:]major.minor.patch[~pre][-revision]`
+///
+/// Versions are ordered by epoch, then major, minor and patch, then pre-release (a pre-release
+/// sorts before the release it precedes, e.g. `1.0.0~rc.1 < 1.0.0`), then revision. A missing
+/// epoch or revision counts as 0.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Version {
+    pub epoch: u64,
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+    /// Dot separated pre-release identifiers, e.g. "rc.1"
+    pub pre: Option<String>,
+    pub revision: Option<u64>,
+}
+
+impl Version {
+    fn invalid(version: &str, reason: &str) -> Error {
+        Error::InvalidVersion(version.to_string(), reason.to_string())
+    }
+
+    /// Parse a version that may leave out the minor and patch numbers (e.g. "2" or "5.2"), as
+    /// used in dependency constraints, missing numbers count as 0
+    pub fn parse_partial(version: &str) -> Result<Version, Error> {
+        Version::parse(version, true)
+    }
+
+    fn parse(version: &str, partial: bool) -> Result<Version, Error> {
+        let (epoch, rest) = match version.split_once(':') {
+            Some((epoch, rest)) => (parse_number(version, epoch, "epoch")?, rest),
+            None => (0, version),
+        };
+
+        let (rest, revision) = match rest.rsplit_once('-') {
+            Some((rest, revision)) => (rest, Some(parse_number(version, revision, "revision")?)),
+            None => (rest, None),
+        };
+
+        let (release, pre) = match rest.split_once('~') {
+            Some((release, pre)) => {
+                let valid = pre.split('.').all(|identifier| {
+                    !identifier.is_empty() && identifier.bytes().all(|b| b.is_ascii_alphanumeric())
+                });
+
+                if !valid {
+                    return Err(Version::invalid(version, "pre-release must be dot separated alphanumeric identifiers"));
+                }
+
+                (release, Some(pre.to_string()))
+            }
+            None => (rest, None),
+        };
+
+        let parts: Vec<&str> = release.split('.').collect();
+        let (major, minor, patch) = match parts.as_slice() {
+            [major, minor, patch] => (*major, *minor, *patch),
+            [major, minor] if partial => (*major, *minor, "0"),
+            [major] if partial => (*major, "0", "0"),
+            _ => return Err(Version::invalid(version, "expected major.minor.patch")),
+        };
+
+        Ok(Version {
+            epoch,
+            major: parse_number(version, major, "major")?,
+            minor: parse_number(version, minor, "minor")?,
+            patch: parse_number(version, patch, "patch")?,
+            pre,
+            revision,
+        })
+    }
+}
+
+/// Parse a numeric version component
+fn parse_number(version: &str, part: &str, name: &str) -> Result<u64, Error> {
+    if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(Version::invalid(version, &format!("{} must be a non-negative integer, found \"{}\"", name, part)));
+    }
+
+    part.parse().map_err(|_| Version::invalid(version, &format!("{} is too large", name)))
+}
+
+impl FromStr for Version {
+    type Err = Error;
+
+    fn from_str(version: &str) -> Result<Self, Self::Err> {
+        Version::parse(version, false)
+    }
+}
+
+impl TryFrom<String> for Version {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Version> for String {
+    fn from(version: Version) -> Self {
+        version.to_string()
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.epoch > 0 {
+            write!(f, "{}:", self.epoch)?;
+        }
+
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+
+        if let Some(pre) = &self.pre {
+            write!(f, "~{}", pre)?;
+        }
+
+        if let Some(revision) = self.revision {
+            write!(f, "-{}", revision)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Compare pre-release identifiers the way semver does: numeric identifiers numerically and
+/// below alphanumeric ones, a shorter list sorting first when it is a prefix of the other
+fn compare_pre(a: &str, b: &str) -> Ordering {
+    let mut a_identifiers = a.split('.');
+    let mut b_identifiers = b.split('.');
+
+    loop {
+        let ordering = match (a_identifiers.next(), b_identifiers.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a), Some(b)) => match (a.parse::<u64>(), b.parse::<u64>()) {
+                (Ok(a), Ok(b)) => a.cmp(&b),
+                (Ok(_), Err(_)) => Ordering::Less,
+                (Err(_), Ok(_)) => Ordering::Greater,
+                (Err(_), Err(_)) => a.cmp(b),
+            },
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.epoch.cmp(&other.epoch)
+            .then(self.major.cmp(&other.major))
+            .then(self.minor.cmp(&other.minor))
+            .then(self.patch.cmp(&other.patch))
+            .then_with(|| match (&self.pre, &other.pre) {
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(a), Some(b)) => compare_pre(a, b),
+            })
+            .then(self.revision.unwrap_or(0).cmp(&other.revision.unwrap_or(0)))
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use super::Version;
+
+    fn version(version: &str) -> Version {
+        version.parse().unwrap_or_else(|e| panic!("{}: {}", version, e))
+    }
+
+    #[test]
+    fn parse() {
+        let cases = [
+            ("1.2.3", (0, 1, 2, 3, None, None)),
+            ("1.2.3-4", (0, 1, 2, 3, None, Some(4))),
+            ("2:1.2.3", (2, 1, 2, 3, None, None)),
+            ("1.2.3~rc.1-2", (0, 1, 2, 3, Some("rc.1"), Some(2))),
+            ("0.0.0", (0, 0, 0, 0, None, None)),
+        ];
+
+        for (input, (epoch, major, minor, patch, pre, revision)) in cases {
+            let parsed = version(input);
+
+            assert_eq!(
+                (parsed.epoch, parsed.major, parsed.minor, parsed.patch, parsed.pre.as_deref(), parsed.revision),
+                (epoch, major, minor, patch, pre, revision),
+                "{}", input,
+            );
+            assert_eq!(parsed.to_string(), input);
+        }
+    }
+
+    #[test]
+    fn parse_rejects() {
+        let cases = [
+            "1.2.x-foo",
+            "1.2.x",
+            "1.2.3-foo",
+            "1.2",
+            "1.2.3.4",
+            "",
+            "-1.2.3",
+            "a:1.2.3",
+            "1.2.3~",
+            "1.2.3~rc..1",
+            "1.2.3~rc-1-1",
+            "1.2.99999999999999999999",
+        ];
+
+        for input in cases {
+            assert!(input.parse::<Version>().is_err(), "{} should be rejected", input);
+        }
+    }
+
+    #[test]
+    fn parse_partial() {
+        let cases = [
+            ("2", "2.0.0"),
+            ("5.2", "5.2.0"),
+            ("1.3.1-2", "1.3.1-2"),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(Version::parse_partial(input).unwrap().to_string(), expected, "{}", input);
+        }
+
+        assert!("5.2".parse::<Version>().is_err());
+    }
+
+    #[test]
+    fn ordering() {
+        let cases = [
+            ("1.0.0", "1.0.1", Ordering::Less),
+            ("1.0.10", "1.0.9", Ordering::Greater),
+            ("1.10.0", "1.9.0", Ordering::Greater),
+            ("2.0.0", "1:1.0.0", Ordering::Less),
+            ("1:1.0.0", "1:1.0.0", Ordering::Equal),
+            ("1.0.0~rc1", "1.0.0", Ordering::Less),
+            ("1.0.0~rc.1", "1.0.0~rc.2", Ordering::Less),
+            ("1.0.0~rc.2", "1.0.0~rc.10", Ordering::Less),
+            ("1.0.0~1", "1.0.0~alpha", Ordering::Less),
+            ("1.0.0~alpha", "1.0.0~alpha.1", Ordering::Less),
+            ("1.0.0~alpha", "1.0.0~beta", Ordering::Less),
+            ("1.0.0~rc1-5", "1.0.0-1", Ordering::Less),
+            ("1.0.0", "1.0.0-0", Ordering::Equal),
+            ("1.0.0", "1.0.0-1", Ordering::Less),
+            ("1.0.0-2", "1.0.0-10", Ordering::Less),
+        ];
+
+        for (a, b, expected) in cases {
+            assert_eq!(version(a).cmp(&version(b)), expected, "{} vs {}", a, b);
+            assert_eq!(version(b).cmp(&version(a)), expected.reverse(), "{} vs {}", b, a);
+        }
+    }
+}